@@ -0,0 +1,195 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, MissedTickBehavior};
+
+use crate::json::{self, Value};
+use crate::stats::PingStats;
+
+/// Bumped whenever the checkpoint layout changes incompatibly.
+const VERSION: f64 = 1.0;
+
+/// Regions that didn't line up between a checkpoint and this run.
+#[derive(Debug, Default, PartialEq)]
+pub struct Mismatch {
+    /// Saved in the checkpoint but not being pinged now; their stats are
+    /// dropped.
+    pub unknown: Vec<String>,
+    /// Pinged now but missing from the checkpoint; they start empty.
+    pub missing: Vec<&'static str>,
+}
+
+/// The whole checkpoint document for `stats`, with times relative to `now`.
+fn render(stats: &[PingStats], now: Instant, saved_at: SystemTime) -> String {
+    let regions: Vec<String> = stats.iter().map(|stat| stat.checkpoint(now)).collect();
+    format!(
+        "{{\"version\":{},\"saved_at\":{},\"regions\":[{}]}}\n",
+        VERSION,
+        json::string(&json::timestamp(saved_at)),
+        regions.join(",")
+    )
+}
+
+/// Restores the stats of every region in the checkpoint `text` that is also
+/// in `stats`, matched by name, as of `now` and `wall_now`.
+fn restore(
+    text: &str,
+    stats: &mut [PingStats<'static>],
+    now: Instant,
+    wall_now: SystemTime,
+) -> Result<Mismatch, String> {
+    let document = json::parse(text)?;
+    if document.get("version").and_then(Value::as_f64) != Some(VERSION) {
+        return Err("unsupported checkpoint version".to_string());
+    }
+    let saved_at = document
+        .get("saved_at")
+        .and_then(Value::as_str)
+        .and_then(json::parse_timestamp)
+        .ok_or("missing or invalid saved_at")?;
+    // A checkpoint from the future, after the clock was set back, is taken
+    // as written just now.
+    let since_saved = wall_now.duration_since(saved_at).unwrap_or(Duration::ZERO);
+    let Some(Value::Array(regions)) = document.get("regions") else {
+        return Err("missing regions".to_string());
+    };
+
+    let mut mismatch = Mismatch::default();
+    let mut restored = Vec::new();
+    for saved in regions {
+        let name = saved
+            .get("region")
+            .and_then(Value::as_str)
+            .ok_or("missing region")?;
+        match stats.iter_mut().find(|stat| stat.region == name) {
+            Some(stat) => {
+                stat.restore(saved, now, since_saved)
+                    .map_err(|err| format!("{}: {}", name, err))?;
+                restored.push(stat.region);
+            }
+            None => mismatch.unknown.push(name.to_string()),
+        }
+    }
+    mismatch.missing = stats
+        .iter()
+        .map(|stat| stat.region)
+        .filter(|region| !restored.contains(region))
+        .collect();
+    Ok(mismatch)
+}
+
+/// Restores `stats` from the checkpoint at `path`. Returns `None` when there
+/// is no checkpoint yet, as on the first run with `--resume`.
+pub fn load(path: &Path, stats: &mut [PingStats<'static>]) -> Result<Option<Mismatch>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("failed to read {}: {}", path.display(), err)),
+    };
+    restore(&text, stats, Instant::now(), SystemTime::now())
+        .map(Some)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Writes a checkpoint of `stats` to `path`. It goes to a temporary file
+/// first and is renamed over the old one, so a crash mid-write leaves the
+/// previous checkpoint intact.
+pub fn save(path: &Path, stats: &[PingStats]) -> io::Result<()> {
+    let mut temporary = OsString::from(path);
+    temporary.push(".tmp");
+    fs::write(&temporary, render(stats, Instant::now(), SystemTime::now()))?;
+    fs::rename(&temporary, path)
+}
+
+/// Saves a checkpoint to `path` every `period`.
+pub fn spawn(
+    path: PathBuf,
+    stats: Arc<Mutex<Vec<PingStats<'static>>>>,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval_at((Instant::now() + period).into(), period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = save(&path, &stats.lock().await) {
+                log::warn!("failed to write checkpoint {}: {}", path.display(), err);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsOptions;
+
+    #[test]
+    fn restores_stats_and_reports_mismatched_regions() {
+        let options = StatsOptions::default();
+        let start = Instant::now();
+        let mut before = vec![
+            PingStats::new("kept", options),
+            PingStats::new("gone", options),
+        ];
+        for (ms, at) in [(Some(10), 0), (None, 1), (Some(30), 2)] {
+            let latency = ms.map(Duration::from_millis);
+            before[0].add_latency_at(latency, start + Duration::from_secs(at));
+        }
+        before[1].add_latency_at(Some(Duration::from_millis(5)), start);
+
+        // Whole seconds, since checkpoints only keep milliseconds.
+        let saved_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let text = render(&before, start + Duration::from_secs(2), saved_at);
+        let mut after = vec![
+            PingStats::new("kept", options),
+            PingStats::new("new", options),
+        ];
+        // Restarted a minute after the checkpoint was written.
+        let now = start + Duration::from_secs(120);
+        let mismatch = restore(&text, &mut after, now, saved_at + Duration::from_secs(60)).unwrap();
+        assert_eq!(
+            mismatch,
+            Mismatch {
+                unknown: vec!["gone".to_string()],
+                missing: vec!["new"],
+            }
+        );
+
+        let (kept, new) = (&after[0], &after[1]);
+        assert_eq!(kept.samples, 2);
+        assert_eq!(kept.avg(), before[0].avg());
+        assert_eq!(kept.loss(), before[0].loss());
+        assert_eq!(kept.session_max(), Some(30.0));
+        assert_eq!(kept.first(), Some(10.0));
+        // The checkpoint was two seconds into the run and the restart a
+        // minute after that.
+        assert_eq!(kept.last_sample(), Some(now - Duration::from_secs(60)));
+        assert_eq!(new.samples, 0);
+    }
+
+    #[test]
+    fn bad_checkpoints_leave_the_stats_alone() {
+        let mut stats = vec![PingStats::new("kept", StatsOptions::default())];
+        stats[0].add_latency(Some(Duration::from_millis(10)));
+        let text = "{\"version\":1,\"saved_at\":\"2024-05-01T00:00:00.000Z\",\
+                    \"regions\":[{\"region\":\"kept\",\"samples\":1,\"latencies\":\"no\"}]}";
+
+        let err = restore(text, &mut stats, Instant::now(), SystemTime::now()).unwrap_err();
+        assert_eq!(err, "kept: missing latencies");
+        assert_eq!(stats[0].samples, 1);
+        assert!(restore(
+            "{\"version\":2}",
+            &mut stats,
+            Instant::now(),
+            SystemTime::now()
+        )
+        .is_err());
+    }
+}
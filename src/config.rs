@@ -0,0 +1,1177 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::export::{self, ExportUnit};
+use crate::json;
+use crate::ping::DEFAULT_RETRIES;
+use crate::prober::PING_TIMEOUT;
+use crate::recommend::ScoreWeights;
+use crate::records::Records;
+use crate::regions::{self, Region};
+use crate::screenshot::ScreenshotFormat;
+use crate::serve;
+use crate::stats::StatsOptions;
+use crate::theme::{self, Theme, ThemePreset};
+use crate::ui::ExtraColumn;
+
+pub const USAGE: &str = "\
+Usage: pong [OPTIONS]
+
+Options:
+      --markdown <PATH>               Write a Markdown table of the final stats to PATH on exit
+      --json <PATH>                   Write the final stats as JSON to PATH on exit
+      --baseline <PATH>               Compare average latencies against a --json report from an
+                                      earlier run
+      --export-unit <ms|s>            Unit of latencies in --json, --ndjson, --record, --serve
+                                      and --snapshot-dir output [default: ms]
+      --records <PATH>                Keep each region's lowest latency ever seen in PATH and
+                                      show it in a Record column
+      --mode <MODE>                   How to measure latency: http (a HEAD request), tcp (a
+                                      handshake), icmp (an echo) or quic (an HTTP/3
+                                      handshake) [default: http]
+      --method <METHOD>               HTTP method: head, get or options; the body of a GET is
+                                      read and discarded [default: head]
+      --timing <ttfb|complete>        Stop the HTTP clock at the first byte of the response or
+                                      once it has been read completely [default: complete]
+      --throughput                    Show the response size and approximate throughput of
+                                      each region's last sample; needs --method get
+      --probe-strategy <avg|min|all>  How results from regions with several endpoints combine
+                                      into one sample [default: avg]
+      --columns <LIST>                Comma-separated extra columns to show: cv, ewma, retries,
+                                      iqr, baseline, sem, mad, error, record
+      --column-widths <LIST>          Comma-separated widths in cells of the region column and
+                                      the ones after it, the last repeating; columns that
+                                      don't fit are hidden from the right
+      --sort <KEY>                    Metric to sort regions by, lowest first: avg, cv, ewma,
+                                      score, or loss and then avg [default: avg]
+      --loss-penalty <MS>             Milliseconds added to the average per percent of loss
+                                      when sorting by score [default: 10]
+      --sort-epsilon <MS>             Treat latencies within about MS of each other as equal
+                                      when sorting, so near ties don't keep swapping
+                                      [default: 0]
+      --config <PATH>                 Read settings from a TOML config file
+      --theme <NAME>                  Color theme: default, mono or high-contrast [default: default]
+      --layout <LAYOUT>               auto, table or stacked; auto stacks each region on two
+                                      lines in terminals under 60 columns [default: auto]
+      --title <TEXT>                  Title of the table, e.g. to tell side-by-side instances
+                                      apart [default: Ping Latencies]
+      --highlight-slowest <N>         Highlight the N slowest regions by the sort key
+                                      [default: 0]
+      --flags                         Show a country flag before each AWS region; needs a
+                                      terminal and font with flag emoji
+      --hint <TEXT>                   Replace the key hint in the footer with TEXT
+      --no-hint                       Hide the key hint in the footer
+      --screenshot-format <txt|svg>   Format of the snapshots saved with the s key [default: txt]
+      --no-color                      Disable colors; also implied by NO_COLOR or a non-TTY stdout
+      --adaptive                      Poll stable regions less often and volatile ones more often
+      --min-interval <DURATION>       Shortest time between pings of a region [default: 1s]
+      --max-interval <DURATION>       Longest time between pings in --adaptive mode [default: 10s]
+      --fast-retry <DURATION>         After a failed ping, ping again this soon instead of
+                                      waiting the usual interval, until one succeeds
+      --stagger <DURATION>            Delay each region's first ping by its position in the
+                                      list times DURATION, e.g. 50ms, to spread out the
+                                      first burst [default: 0s]
+      --timeout <DURATION>            How long a ping may take before it counts as failed;
+                                      regions files can set it per region [default: 3s]
+      --retries <N>                   Retry a failed ping up to N times before counting it
+                                      as lost; 0 reports failures at once [default: 3]
+      --hide-unreachable              Hide regions with 100% loss over a full window; they are
+                                      still pinged slowly
+      --percentile <METHOD>           Percentile convention: nearest-rank or linear
+                                      [default: nearest-rank]
+      --dns-ttl <DURATION>            Resolve hostnames once and re-resolve this often; 0 resolves
+                                      on every new connection [default: 5m]
+      --recommend-weights <LAT,LOSS>  Weights of median latency (ms) and loss (%) when scoring
+                                      the recommended region [default: 1,10]
+      --recommend-window <DURATION>   How far back the recommendation looks [default: 5m]
+      --snapshot-dir <DIR>            Write the stats as a --json report to a new file in DIR
+                                      every --snapshot-interval, for long unattended runs
+      --snapshot-interval <DURATION>  How often --snapshot-dir gets a snapshot [default: 1m]
+      --snapshot-keep <N>             Snapshots kept in --snapshot-dir; the oldest are deleted
+                                      [default: 60]
+      --resume <FILE>                 Restore the stats saved in FILE, if it exists, and save
+                                      them there every --checkpoint-interval and on exit
+      --checkpoint-interval <DURATION>
+                                      How often --resume saves the stats [default: 1m]
+      --otlp <URL>                    Push latency and loss metrics to an OTLP/HTTP collector
+      --otlp-interval <DURATION>      How often metrics are pushed to --otlp [default: 10s]
+      --serve <[ADDR:]PORT>           Serve the current stats as JSON at /stats; a bare port
+                                      listens on localhost only
+      --histogram-bins <N>            Number of bins in the distribution view [default: 10]
+      --smooth <N>                    Average the detail panel's trend line over N samples
+                                      [default: 1]
+      --window <DURATION>             Compute statistics over the samples from the last
+                                      DURATION instead of the last 100 samples
+      --ewma-alpha <ALPHA>            Weight of the newest sample in the EWMA column, in (0, 1]
+                                      [default: 0.2]
+      --regions <PATH>                Probe the regions listed in PATH, or - for stdin, instead
+                                      of the AWS ones; see the README for the format
+      --hosts <HOSTS>                 Probe a comma-separated list of HOST or HOST:PORT entries
+                                      instead of the AWS ones, over TCP unless --mode is given
+      --discover <SERVICE>            Probe every public AWS region that offers SERVICE, e.g.
+                                      dynamodb or s3, from AWS's published endpoint list;
+                                      the list is cached for a day
+      --only <LIST>                   Probe only the comma-separated regions, each given by
+                                      name, code or location, e.g. us-east-1,Ireland
+      --exclude <LIST>                Skip regions whose name, code or location starts with any
+                                      of the comma-separated prefixes, e.g. cn-,us-gov-;
+                                      applies after --only
+      --path <PATH>                   Replace the URL path of every region with PATH
+      --precision <N>                 Decimal places of latencies in the table, details and
+                                      Markdown report, from 0 to 6 [default: 2]
+      --gradient                      Color last latencies from green to red by where they fall
+                                      between the table's fastest and slowest
+      --spike-blink <FACTOR>          Blink a region's last latency for a few seconds when it is
+                                      at least FACTOR times its average, e.g. 3
+      --compact                       Show table latencies of a second or more in seconds,
+                                      e.g. 1.2 s, to keep the columns narrow
+      --window-fill                   Show how full each region's 100-sample window is, e.g.
+                                      43/100, until it fills; has no effect with --window
+      --discard-warmup <N>            Drop each region's first N samples, failed ones included,
+                                      so cold connections don't skew the stats; they appear
+                                      N samples later [default: 0]
+      --min-samples <N>               Show ~ instead of statistics until a region has N samples
+                                      [default: 0]
+      --insecure                      Accept invalid TLS certificates, e.g. self-signed ones on
+                                      custom regions
+      --record <PATH>                 Save every sample to PATH in the format --replay reads
+      --replay <PATH>                 Replay samples recorded with --record or --ndjson instead
+                                      of pinging
+      --replay-speed <N>              Play a --replay capture N times faster [default: 1]
+      --warmup <DURATION>             Show a countdown instead of the table for DURATION while
+                                      connections warm up, then start the stats over
+      --run-for <DURATION>            Quit after DURATION, writing --markdown and --json reports
+                                      as on a normal exit
+      --storm <DURATION>              After DURATION, probe every region 4 times at once every
+                                      200ms for DURATION, then report how median latency
+                                      changed during and after; S starts one any time
+      --notify                        Ring the terminal bell when connectivity comes back after
+                                      every region failed
+      --sonar                         Beep for the selected region, faster the lower its
+                                      latency; b toggles the beeps
+      --preflight                     Ping every region once, print which answered and exit;
+                                      fails if fewer than --preflight-min did
+      --preflight-min <N>             Regions that must answer for --preflight to succeed
+                                      [default: 1]
+      --print-config                  Print the settings in effect after merging defaults, the
+                                      config file and flags, then exit
+      --no-tui                        Run without the interactive table; stop with Ctrl+C
+      --ndjson                        Stream one JSON object per sample to stdout; implies --no-tui
+  -h, --help                          Print this help and exit
+
+Exit status:
+  0  Success
+  1  A runtime error, or too few regions answered --preflight
+  2  Invalid arguments or --replay capture
+  3  With --run-for, --markdown or --json: some regions never answered
+  4  With --run-for, --markdown or --json: no region answered
+";
+
+/// The footer's key hint unless replaced with `--hint` or hidden with
+/// `--no-hint`.
+const DEFAULT_HINT: &str =
+    "Press q or Ctrl+C to quit, ↑/↓ to select, p to pin, r to re-ping, ? for every key.";
+
+/// Most decimal places `--precision` accepts; HTTP timings aren't meaningful
+/// below a nanosecond.
+const MAX_PRECISION: usize = 6;
+
+/// The request `--mode http` sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpMethod {
+    #[default]
+    Head,
+    Get,
+    Options,
+}
+
+impl FromStr for HttpMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "head" => Ok(HttpMethod::Head),
+            "get" => Ok(HttpMethod::Get),
+            "options" => Ok(HttpMethod::Options),
+            _ => Err(format!(
+                "invalid method '{}', expected head, get or options",
+                s
+            )),
+        }
+    }
+}
+
+/// Where `--mode http` stops the clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpTiming {
+    /// Once the status line and headers have arrived, the closest to network
+    /// round trip an HTTP request gets.
+    Ttfb,
+    /// Once the whole response, body included, has been read.
+    #[default]
+    Complete,
+}
+
+impl FromStr for HttpTiming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ttfb" => Ok(HttpTiming::Ttfb),
+            "complete" => Ok(HttpTiming::Complete),
+            _ => Err(format!("invalid timing '{}', expected ttfb or complete", s)),
+        }
+    }
+}
+
+/// How the endpoints of a region with more than one URL are combined per tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProbeStrategy {
+    /// Average the round trips of the endpoints that answered.
+    #[default]
+    Avg,
+    /// Keep only the fastest endpoint.
+    Min,
+    /// Record every endpoint as its own sample.
+    All,
+}
+
+impl FromStr for ProbeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "avg" => Ok(ProbeStrategy::Avg),
+            "min" => Ok(ProbeStrategy::Min),
+            "all" => Ok(ProbeStrategy::All),
+            _ => Err(format!(
+                "invalid probe strategy '{}', expected avg, min or all",
+                s
+            )),
+        }
+    }
+}
+
+/// How each endpoint's round trip is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProbeMode {
+    /// Time a `HEAD` request.
+    #[default]
+    Http,
+    /// Time a TCP handshake with the endpoint's host and port.
+    Tcp,
+    /// Time an ICMP echo to the endpoint's host.
+    Icmp,
+    /// Time a QUIC handshake offering HTTP/3.
+    Quic,
+}
+
+impl FromStr for ProbeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(ProbeMode::Http),
+            "tcp" => Ok(ProbeMode::Tcp),
+            "icmp" => Ok(ProbeMode::Icmp),
+            "quic" => Ok(ProbeMode::Quic),
+            _ => Err(format!(
+                "invalid mode '{}', expected http, tcp, icmp or quic",
+                s
+            )),
+        }
+    }
+}
+
+/// Metric the table is sorted by, ascending.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Avg,
+    /// Coefficient of variation, for finding the most stable region.
+    Cv,
+    /// Exponentially-weighted moving average, which tracks recent shifts.
+    Ewma,
+    /// Average latency plus `--loss-penalty` milliseconds per percent of loss.
+    Score,
+    /// Loss, with ties broken by average latency.
+    Loss,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "avg" => Ok(SortKey::Avg),
+            "cv" => Ok(SortKey::Cv),
+            "ewma" => Ok(SortKey::Ewma),
+            "score" => Ok(SortKey::Score),
+            "loss" => Ok(SortKey::Loss),
+            _ => Err(format!(
+                "invalid sort key '{}', expected avg, cv, ewma, score or loss",
+                s
+            )),
+        }
+    }
+}
+
+/// How regions are laid out in the TUI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// The table, switching to stacked in narrow terminals.
+    #[default]
+    Auto,
+    Table,
+    /// Each region as a two-line block, for tmux splits and phone screens.
+    Stacked,
+}
+
+impl FromStr for LayoutMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(LayoutMode::Auto),
+            "table" => Ok(LayoutMode::Table),
+            "stacked" => Ok(LayoutMode::Stacked),
+            _ => Err(format!(
+                "invalid layout '{}', expected auto, table or stacked",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub help: bool,
+    pub markdown: Option<PathBuf>,
+    pub json: Option<PathBuf>,
+    /// Average latency per region from the `--baseline` report.
+    pub baseline: Option<HashMap<String, Option<f64>>>,
+    /// All-time lowest latencies from the `--records` file.
+    pub records: Option<Records>,
+    pub export_unit: ExportUnit,
+    pub mode: ProbeMode,
+    pub method: HttpMethod,
+    pub timing: HttpTiming,
+    pub throughput: bool,
+    pub probe_strategy: ProbeStrategy,
+    pub columns: Vec<ExtraColumn>,
+    /// Fixed column widths in cells from `--column-widths`, region column
+    /// first; the last one repeats for any further columns.
+    pub column_widths: Option<Vec<u16>>,
+    pub sort: SortKey,
+    pub loss_penalty: f64,
+    pub sort_epsilon: f64,
+    pub theme: Theme,
+    pub theme_preset: ThemePreset,
+    pub layout: LayoutMode,
+    pub title: String,
+    /// Names shown in place of region names, from the config file's
+    /// `[names]` section. Sorting, matching and reports keep the originals.
+    pub display_names: HashMap<String, String>,
+    pub flags: bool,
+    pub highlight_slowest: usize,
+    pub hint: Option<String>,
+    pub screenshot_format: ScreenshotFormat,
+    pub adaptive: bool,
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    pub fast_retry: Option<Duration>,
+    /// How much later than the one before it each region is first pinged.
+    pub stagger: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub hide_unreachable: bool,
+    pub stats: StatsOptions,
+    pub dns_ttl: Duration,
+    pub recommend_weights: ScoreWeights,
+    pub snapshot_dir: Option<PathBuf>,
+    pub snapshot_interval: Duration,
+    pub snapshot_keep: usize,
+    pub resume: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    pub otlp: Option<String>,
+    pub otlp_interval: Duration,
+    pub serve: Option<SocketAddr>,
+    pub histogram_bins: usize,
+    pub smooth: usize,
+    pub no_tui: bool,
+    pub ndjson: bool,
+    pub regions: Vec<Region>,
+    /// The AWS service whose regions `--discover` looks up at startup.
+    pub discover: Option<String>,
+    /// `--only` and `--exclude`, kept to apply to discovered regions.
+    pub region_filter: RegionFilter,
+    /// The path from `--path`, kept to apply to discovered regions.
+    pub probe_path: Option<String>,
+    pub precision: usize,
+    pub compact: bool,
+    pub gradient: bool,
+    pub min_samples: u64,
+    pub window_fill: bool,
+    pub insecure: bool,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub replay_speed: f64,
+    pub notify: bool,
+    pub sonar: bool,
+    pub warmup: Duration,
+    pub run_for: Option<Duration>,
+    pub storm: Option<Duration>,
+    pub preflight: bool,
+    pub preflight_min: usize,
+    pub print_config: bool,
+}
+
+/// The regions picked with `--only` and `--exclude`.
+#[derive(Debug, Clone, Default)]
+pub struct RegionFilter {
+    pub only: Option<String>,
+    pub exclude: Option<String>,
+}
+
+impl RegionFilter {
+    /// Drops the regions filtered out, failing if none are left.
+    pub fn apply(&self, list: &mut Vec<Region>) -> Result<(), String> {
+        if let Some(only) = &self.only {
+            list.retain(|region| {
+                only.split(',')
+                    .any(|query| regions::matches(region.name, query))
+            });
+            if list.is_empty() {
+                return Err(format!("--only '{}' matched no regions", only));
+            }
+        }
+        // Applied after `--only`, so it can trim a broad allowlist.
+        if let Some(exclude) = &self.exclude {
+            list.retain(|region| {
+                !exclude
+                    .split(',')
+                    .any(|prefix| regions::matches_prefix(region.name, prefix))
+            });
+            if list.is_empty() {
+                return Err(format!("--exclude '{}' left no regions", exclude));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            help: false,
+            markdown: None,
+            json: None,
+            baseline: None,
+            records: None,
+            export_unit: ExportUnit::default(),
+            mode: ProbeMode::default(),
+            method: HttpMethod::default(),
+            timing: HttpTiming::default(),
+            throughput: false,
+            probe_strategy: ProbeStrategy::default(),
+            columns: Vec::new(),
+            column_widths: None,
+            sort: SortKey::default(),
+            loss_penalty: 10.0,
+            sort_epsilon: 0.0,
+            theme: Theme::default(),
+            theme_preset: ThemePreset::default(),
+            layout: LayoutMode::default(),
+            title: "Ping Latencies".to_string(),
+            display_names: HashMap::new(),
+            flags: false,
+            highlight_slowest: 0,
+            hint: Some(DEFAULT_HINT.to_string()),
+            screenshot_format: ScreenshotFormat::default(),
+            adaptive: false,
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(10),
+            fast_retry: None,
+            stagger: Duration::ZERO,
+            timeout: PING_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            hide_unreachable: false,
+            stats: StatsOptions::default(),
+            dns_ttl: Duration::from_secs(300),
+            recommend_weights: ScoreWeights::default(),
+            snapshot_dir: None,
+            snapshot_interval: Duration::from_secs(60),
+            snapshot_keep: 60,
+            resume: None,
+            checkpoint_interval: Duration::from_secs(60),
+            otlp: None,
+            otlp_interval: Duration::from_secs(10),
+            serve: None,
+            histogram_bins: 10,
+            smooth: 1,
+            no_tui: false,
+            ndjson: false,
+            regions: regions::builtin(None).expect("built-in region URLs are valid"),
+            discover: None,
+            region_filter: RegionFilter::default(),
+            probe_path: None,
+            precision: 2,
+            compact: false,
+            gradient: false,
+            min_samples: 0,
+            window_fill: false,
+            insecure: false,
+            record: None,
+            replay: None,
+            replay_speed: 1.0,
+            notify: false,
+            sonar: false,
+            warmup: Duration::ZERO,
+            run_for: None,
+            storm: None,
+            preflight: false,
+            preflight_min: 1,
+            print_config: false,
+        }
+    }
+}
+
+/// Longest duration any option accepts. Deadlines are computed by adding
+/// durations to the current instant, which overflows long before
+/// `Duration::MAX`.
+const MAX_DURATION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Parses durations like `500ms`, `2s`, `1.5m` or `1h`. A bare number is
+/// taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid duration unit in '{}'", s)),
+    };
+    match Duration::try_from_secs_f64(secs) {
+        Ok(duration) if duration <= MAX_DURATION => Ok(duration),
+        _ => Err(format!("duration '{}' is out of range", s)),
+    }
+}
+
+/// Parses `--column-widths`, a comma-separated list of widths in cells.
+fn parse_column_widths(s: &str) -> Result<Vec<u16>, String> {
+    s.split(',')
+        .map(|width| match width.trim().parse() {
+            Ok(width) if width > 0 => Ok(width),
+            _ => Err(format!(
+                "invalid column widths '{}', expected positive integers like 28,10",
+                s
+            )),
+        })
+        .collect()
+}
+
+/// Sections of a parsed config file, keyed by section name. Keys before the
+/// first `[section]` header live under `""`.
+pub type ConfigFile = HashMap<String, HashMap<String, String>>;
+
+/// Parses the small subset of TOML pong's config file needs: `[section]`
+/// headers, `key = value` pairs with quoted or bare keys and values, and `#`
+/// comments.
+pub fn parse_config_file(text: &str) -> Result<ConfigFile, String> {
+    let mut sections = ConfigFile::new();
+    let mut section = String::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split_once(" #").map_or(line, |(line, _)| line).trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?;
+        // Keys and values alike may be quoted, as keys with spaces must be.
+        let unquote = |s: &str| {
+            let s = s.trim();
+            s.strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(s)
+                .to_string()
+        };
+
+        sections
+            .entry(section.clone())
+            .or_default()
+            .insert(unquote(key), unquote(value));
+    }
+
+    Ok(sections)
+}
+
+impl Config {
+    /// The name a region is shown under: its `[names]` override, if any, or
+    /// else its own name. Overrides match the full name or, for the built-in
+    /// regions, just the code or location, e.g. `us-east-1` or `Virginia`.
+    pub fn display_name<'a>(&'a self, region: &'a str) -> &'a str {
+        self.display_names
+            .get(region)
+            .or_else(|| {
+                self.display_names
+                    .iter()
+                    .find(|(key, _)| regions::matches(region, key))
+                    .map(|(_, name)| name)
+            })
+            .map_or(region, String::as_str)
+    }
+
+    /// The settings in effect, as TOML, for checking how defaults, the config
+    /// file and flags combined. Only what the config file accepts is printed
+    /// as settings, so the output can be used as one; the rest can only be
+    /// set with flags and is shown commented out. Unset options are left out.
+    pub fn effective_toml(&self) -> String {
+        // Every option enum's command-line name is its variant's name in
+        // kebab case, e.g. `NearestRank` for `nearest-rank`.
+        fn name(value: impl std::fmt::Debug) -> String {
+            let mut name = String::new();
+            for (i, c) in format!("{:?}", value).chars().enumerate() {
+                if c.is_uppercase() && i > 0 {
+                    name.push('-');
+                }
+                name.push(c.to_ascii_lowercase());
+            }
+            json::string(&name)
+        }
+        fn secs(duration: Duration) -> String {
+            json::string(&format!("{}s", duration.as_secs_f64()))
+        }
+        fn path(path: &std::path::Path) -> String {
+            json::string(&path.display().to_string())
+        }
+
+        let mut flag_only = vec![
+            format!("mode = {}", name(self.mode)),
+            format!("method = {}", name(self.method)),
+            format!("timing = {}", name(self.timing)),
+            format!("export_unit = {}", name(self.export_unit)),
+            format!("probe_strategy = {}", name(self.probe_strategy)),
+            format!("timeout = {}", secs(self.timeout)),
+            format!("retries = {}", self.retries),
+            format!("stagger = {}", secs(self.stagger)),
+            format!("min_interval = {}", secs(self.min_interval)),
+            format!("max_interval = {}", secs(self.max_interval)),
+            format!("adaptive = {}", self.adaptive),
+            format!("sort = {}", name(self.sort)),
+            format!("loss_penalty = {}", self.loss_penalty),
+            format!("sort_epsilon = {}", self.sort_epsilon),
+            format!(
+                "columns = [{}]",
+                self.columns.iter().map(name).collect::<Vec<_>>().join(", ")
+            ),
+            format!("layout = {}", name(self.layout)),
+            format!("title = {}", json::string(&self.title)),
+            format!("precision = {}", self.precision),
+            format!("compact = {}", self.compact),
+            format!("gradient = {}", self.gradient),
+            format!("percentile = {}", name(self.stats.percentile_method)),
+            format!("ewma_alpha = {}", self.stats.ewma_alpha),
+            format!("recommend_window = {}", secs(self.stats.history_window)),
+            format!("dns_ttl = {}", secs(self.dns_ttl)),
+            format!("discard_warmup = {}", self.stats.discard_warmup),
+            format!("min_samples = {}", self.min_samples),
+            format!("window_fill = {}", self.window_fill),
+            format!("warmup = {}", secs(self.warmup)),
+            format!("checkpoint_interval = {}", secs(self.checkpoint_interval)),
+            format!("no_tui = {}", self.no_tui),
+            format!("ndjson = {}", self.ndjson),
+            format!("insecure = {}", self.insecure),
+        ];
+        let optional = [
+            ("window", self.stats.window.map(secs)),
+            (
+                "spike_blink",
+                self.stats.spike_factor.map(|f| f.to_string()),
+            ),
+            ("fast_retry", self.fast_retry.map(secs)),
+            ("run_for", self.run_for.map(secs)),
+            ("storm", self.storm.map(secs)),
+            ("markdown", self.markdown.as_deref().map(path)),
+            ("json", self.json.as_deref().map(path)),
+            ("record", self.record.as_deref().map(path)),
+            ("replay", self.replay.as_deref().map(path)),
+            ("snapshot_dir", self.snapshot_dir.as_deref().map(path)),
+            ("resume", self.resume.as_deref().map(path)),
+            ("records", self.records.as_ref().map(|r| path(r.path()))),
+            ("discover", self.discover.as_deref().map(json::string)),
+            ("otlp", self.otlp.as_deref().map(json::string)),
+            (
+                "serve",
+                self.serve.map(|addr| json::string(&addr.to_string())),
+            ),
+        ];
+        flag_only.extend(
+            optional
+                .into_iter()
+                .filter_map(|(key, value)| value.map(|value| format!("{} = {}", key, value))),
+        );
+        let names: Vec<String> = self.regions.iter().map(|r| json::string(r.name)).collect();
+        flag_only.push(format!("{} regions:", names.len()));
+        flag_only.push(format!("regions = [{}]", names.join(", ")));
+
+        let mut lines = vec![format!("theme = {}", name(self.theme_preset))];
+        if let Some(widths) = &self.column_widths {
+            let widths: Vec<String> = widths.iter().map(u16::to_string).collect();
+            lines.push(format!(
+                "column_widths = {}",
+                json::string(&widths.join(","))
+            ));
+        }
+        lines.push("\n# Set by flags only; a config file can't change these.".to_string());
+        lines.extend(flag_only.into_iter().map(|line| format!("# {}", line)));
+
+        if !self.display_names.is_empty() {
+            let mut names: Vec<_> = self.display_names.iter().collect();
+            names.sort();
+            lines.push("\n[names]".to_string());
+            lines.extend(names.into_iter().map(|(region, name)| {
+                format!("{} = {}", json::string(region), json::string(name))
+            }));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        let mut config_path = None;
+        let mut theme_preset = None;
+        let mut column_widths = None;
+        let mut no_color = false;
+        let mut regions_path = None;
+        let mut baseline_path = None;
+        let mut records_path = None;
+        let mut hosts = None;
+        let mut mode = None;
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} requires a value", flag))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => config.help = true,
+                "--markdown" => config.markdown = Some(PathBuf::from(value()?)),
+                "--json" => config.json = Some(PathBuf::from(value()?)),
+                "--baseline" => baseline_path = Some(PathBuf::from(value()?)),
+                "--records" => records_path = Some(PathBuf::from(value()?)),
+                "--export-unit" => config.export_unit = value()?.parse()?,
+                "--mode" => mode = Some(value()?.parse()?),
+                "--method" => config.method = value()?.parse()?,
+                "--timing" => config.timing = value()?.parse()?,
+                "--throughput" => config.throughput = true,
+                "--probe-strategy" => config.probe_strategy = value()?.parse()?,
+                "--columns" => {
+                    config.columns = value()?
+                        .split(',')
+                        .map(|column| column.trim().parse())
+                        .collect::<Result<_, _>>()?
+                }
+                "--column-widths" => column_widths = Some(value()?),
+                "--sort" => config.sort = value()?.parse()?,
+                "--loss-penalty" => {
+                    config.loss_penalty = match value()?.parse() {
+                        Ok(penalty) if penalty >= 0.0 && f64::is_finite(penalty) => penalty,
+                        _ => return Err("--loss-penalty must be a non-negative number".to_string()),
+                    }
+                }
+                "--sort-epsilon" => {
+                    config.sort_epsilon = match value()?.parse() {
+                        Ok(epsilon) if epsilon >= 0.0 && f64::is_finite(epsilon) => epsilon,
+                        _ => return Err("--sort-epsilon must be a non-negative number".to_string()),
+                    }
+                }
+                "--config" => config_path = Some(PathBuf::from(value()?)),
+                "--theme" => theme_preset = Some(value()?.parse::<ThemePreset>()?),
+                "--no-color" => no_color = true,
+                "--layout" => config.layout = value()?.parse()?,
+                "--screenshot-format" => config.screenshot_format = value()?.parse()?,
+                "--title" => config.title = value()?,
+                "--flags" => config.flags = true,
+                "--highlight-slowest" => {
+                    config.highlight_slowest = value()?.parse().map_err(|_| {
+                        "--highlight-slowest must be a non-negative integer".to_string()
+                    })?
+                }
+                "--hint" => config.hint = Some(value()?),
+                "--no-hint" => config.hint = None,
+                "--adaptive" => config.adaptive = true,
+                "--min-interval" => config.min_interval = parse_duration(&value()?)?,
+                "--max-interval" => config.max_interval = parse_duration(&value()?)?,
+                "--fast-retry" => config.fast_retry = Some(parse_duration(&value()?)?),
+                "--stagger" => config.stagger = parse_duration(&value()?)?,
+                "--timeout" => config.timeout = parse_duration(&value()?)?,
+                "--retries" => {
+                    config.retries = value()?
+                        .parse()
+                        .map_err(|_| "--retries must be a non-negative integer".to_string())?
+                }
+                "--hide-unreachable" => config.hide_unreachable = true,
+                "--percentile" => config.stats.percentile_method = value()?.parse()?,
+                "--dns-ttl" => config.dns_ttl = parse_duration(&value()?)?,
+                "--recommend-weights" => config.recommend_weights = value()?.parse()?,
+                "--recommend-window" => config.stats.history_window = parse_duration(&value()?)?,
+                "--snapshot-dir" => config.snapshot_dir = Some(PathBuf::from(value()?)),
+                "--snapshot-interval" => config.snapshot_interval = parse_duration(&value()?)?,
+                "--snapshot-keep" => {
+                    config.snapshot_keep = match value()?.parse() {
+                        Ok(keep) if keep > 0 => keep,
+                        _ => return Err("--snapshot-keep must be a positive integer".to_string()),
+                    }
+                }
+                "--resume" => config.resume = Some(PathBuf::from(value()?)),
+                "--checkpoint-interval" => config.checkpoint_interval = parse_duration(&value()?)?,
+                "--otlp" => config.otlp = Some(value()?),
+                "--otlp-interval" => config.otlp_interval = parse_duration(&value()?)?,
+                "--serve" => config.serve = Some(serve::parse_address(&value()?)?),
+                "--histogram-bins" => {
+                    config.histogram_bins = match value()?.parse() {
+                        Ok(bins) if bins > 0 => bins,
+                        _ => return Err("--histogram-bins must be a positive integer".to_string()),
+                    }
+                }
+                "--smooth" => {
+                    config.smooth = match value()?.parse() {
+                        Ok(window) if window > 0 => window,
+                        _ => return Err("--smooth must be a positive integer".to_string()),
+                    }
+                }
+                "--window" => config.stats.window = Some(parse_duration(&value()?)?),
+                "--ewma-alpha" => {
+                    let alpha: f64 = value()?
+                        .parse()
+                        .map_err(|_| "--ewma-alpha must be a number".to_string())?;
+                    if !(alpha > 0.0 && alpha <= 1.0) {
+                        return Err("--ewma-alpha must be in (0, 1]".to_string());
+                    }
+                    config.stats.ewma_alpha = alpha;
+                }
+                "--regions" => regions_path = Some(PathBuf::from(value()?)),
+                "--hosts" => hosts = Some(value()?),
+                "--discover" => config.discover = Some(value()?),
+                "--only" => config.region_filter.only = Some(value()?),
+                "--exclude" => config.region_filter.exclude = Some(value()?),
+                "--path" => config.probe_path = Some(value()?),
+                "--precision" => {
+                    config.precision = match value()?.parse() {
+                        Ok(precision) if precision <= MAX_PRECISION => precision,
+                        _ => {
+                            return Err(format!(
+                                "--precision must be an integer from 0 to {}",
+                                MAX_PRECISION
+                            ))
+                        }
+                    }
+                }
+                "--discard-warmup" => {
+                    config.stats.discard_warmup = value()?.parse().map_err(|_| {
+                        "--discard-warmup must be a non-negative integer".to_string()
+                    })?
+                }
+                "--min-samples" => {
+                    config.min_samples = value()?
+                        .parse()
+                        .map_err(|_| "--min-samples must be a non-negative integer".to_string())?
+                }
+                "--insecure" => config.insecure = true,
+                "--record" => config.record = Some(PathBuf::from(value()?)),
+                "--replay" => config.replay = Some(PathBuf::from(value()?)),
+                "--replay-speed" => {
+                    config.replay_speed = match value()?.parse() {
+                        Ok(speed) if speed > 0.0 && f64::is_finite(speed) => speed,
+                        _ => return Err("--replay-speed must be a positive number".to_string()),
+                    }
+                }
+                "--notify" => config.notify = true,
+                "--sonar" => config.sonar = true,
+                "--compact" => config.compact = true,
+                "--gradient" => config.gradient = true,
+                "--window-fill" => config.window_fill = true,
+                "--spike-blink" => {
+                    config.stats.spike_factor = match value()?.parse() {
+                        Ok(factor) if factor > 1.0 && f64::is_finite(factor) => Some(factor),
+                        _ => return Err("--spike-blink must be a number above 1".to_string()),
+                    }
+                }
+                "--warmup" => config.warmup = parse_duration(&value()?)?,
+                "--storm" => config.storm = Some(parse_duration(&value()?)?),
+                "--run-for" => config.run_for = Some(parse_duration(&value()?)?),
+                "--preflight" => config.preflight = true,
+                "--print-config" => config.print_config = true,
+                "--preflight-min" => {
+                    config.preflight_min = value()?
+                        .parse()
+                        .map_err(|_| "--preflight-min must be a non-negative integer".to_string())?
+                }
+                "--no-tui" => config.no_tui = true,
+                "--ndjson" => {
+                    // The stream owns stdout, so there's no table to draw.
+                    config.ndjson = true;
+                    config.no_tui = true;
+                }
+                _ => return Err(format!("unknown argument: {}", flag)),
+            }
+        }
+
+        // Ad-hoc hosts are rarely web servers, so they get a handshake rather
+        // than an HTTP request unless a mode is asked for.
+        config.mode = match mode {
+            Some(mode) => mode,
+            None if hosts.is_some() => ProbeMode::Tcp,
+            None => ProbeMode::default(),
+        };
+
+        if config.timeout.is_zero() {
+            return Err("--timeout must be greater than zero".to_string());
+        }
+        if config.stats.window.is_some_and(|window| window.is_zero()) {
+            return Err("--window must be greater than zero".to_string());
+        }
+        if config.snapshot_interval.is_zero() {
+            return Err("--snapshot-interval must be greater than zero".to_string());
+        }
+        if config.checkpoint_interval.is_zero() {
+            return Err("--checkpoint-interval must be greater than zero".to_string());
+        }
+        if config.otlp_interval.is_zero() {
+            return Err("--otlp-interval must be greater than zero".to_string());
+        }
+        if config.min_interval > config.max_interval {
+            return Err("--min-interval must not exceed --max-interval".to_string());
+        }
+
+        if hosts.is_some() && regions_path.is_some() {
+            return Err("--hosts can't be combined with --regions".to_string());
+        }
+        if config.discover.is_some() {
+            if hosts.is_some() || regions_path.is_some() {
+                return Err("--discover can't be combined with --regions or --hosts".to_string());
+            }
+            if config.replay.is_some() {
+                return Err("--discover can't be combined with --replay".to_string());
+            }
+        }
+        let probe_path = config.probe_path.clone();
+        config.regions = match &regions_path {
+            // `-` reads the list from stdin, before the TUI takes over the
+            // terminal; key presses are read from the TTY directly.
+            Some(path) if path.as_os_str() == "-" => {
+                let text = io::read_to_string(io::stdin())
+                    .map_err(|err| format!("failed to read regions from stdin: {}", err))?;
+                regions::parse_regions_file(&text, probe_path.as_deref())
+                    .map_err(|err| format!("stdin: {}", err))?
+            }
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+                regions::parse_regions_file(&text, probe_path.as_deref())
+                    .map_err(|err| format!("{}: {}", path.display(), err))?
+            }
+            None => match &hosts {
+                Some(hosts) => regions::from_hosts(hosts, probe_path.as_deref())?,
+                None => regions::builtin(probe_path.as_deref())?,
+            },
+        };
+        // Discovered regions are filtered once they're known.
+        if config.discover.is_none() {
+            config.region_filter.apply(&mut config.regions)?;
+        }
+
+        // Throughput needs a body to measure and the time it took to read it.
+        if config.throughput
+            && (config.mode != ProbeMode::Http
+                || config.method != HttpMethod::Get
+                || config.timing != HttpTiming::Complete)
+        {
+            return Err(
+                "--throughput needs --mode http, --method get and --timing complete".to_string(),
+            );
+        }
+
+        if !config.warmup.is_zero() && config.no_tui {
+            return Err("--warmup needs the TUI, it can't be combined with --no-tui".to_string());
+        }
+
+        if config.preflight && config.replay.is_some() {
+            return Err("--preflight can't be combined with --replay".to_string());
+        }
+        if let Some(storm) = config.storm {
+            if storm.is_zero() {
+                return Err("--storm must be greater than zero".to_string());
+            }
+            if config.replay.is_some() {
+                return Err("--storm can't be combined with --replay".to_string());
+            }
+            // The comparison reads the samples from before the storm back
+            // out of the history once it has settled.
+            if storm * 3 > config.stats.history_window {
+                return Err("--storm must be at most a third of --recommend-window".to_string());
+            }
+        }
+        if config.resume.is_some() {
+            if config.replay.is_some() {
+                return Err("--resume can't be combined with --replay".to_string());
+            }
+            // The warm-up ends by starting the stats over, resumed ones too.
+            if !config.warmup.is_zero() {
+                return Err("--resume can't be combined with --warmup".to_string());
+            }
+        }
+
+        if let Some(path) = &baseline_path {
+            config.baseline = Some(export::load_baseline(path)?);
+            if !config.columns.contains(&ExtraColumn::Baseline) {
+                config.columns.push(ExtraColumn::Baseline);
+            }
+        }
+        if let Some(path) = &records_path {
+            config.records = Some(Records::load(path)?);
+            if !config.columns.contains(&ExtraColumn::Record) {
+                config.columns.push(ExtraColumn::Record);
+            }
+        }
+
+        let file = match &config_path {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+                parse_config_file(&text).map_err(|err| format!("{}: {}", path.display(), err))?
+            }
+            None => ConfigFile::new(),
+        };
+
+        let theme_preset = match theme_preset {
+            Some(preset) => preset,
+            None => match file.get("").and_then(|top| top.get("theme")) {
+                Some(name) => name.parse()?,
+                None => ThemePreset::default(),
+            },
+        };
+        config.theme = Theme::preset(theme_preset);
+        config.theme_preset = theme_preset;
+        let column_widths = column_widths.as_deref().or_else(|| {
+            file.get("")
+                .and_then(|top| top.get("column_widths"))
+                .map(String::as_str)
+        });
+        if let Some(widths) = column_widths {
+            config.column_widths = Some(parse_column_widths(widths)?);
+        }
+        if let Some(section) = file.get("theme") {
+            config.theme.apply_overrides(section)?;
+        }
+        if let Some(section) = file.get("names") {
+            config.display_names = section.clone();
+        }
+        if !theme::supports_truecolor() {
+            config.theme.downsample();
+        }
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if no_color || no_color_env || !std::io::stdout().is_terminal() {
+            config.theme = Theme::plain();
+            config.gradient = false;
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10000000000000000000s").is_err());
+        assert_eq!(
+            parse_duration("100000000000000000000000s"),
+            Err("duration '100000000000000000000000s' is out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn config_file_keys_may_be_quoted() {
+        let text = "[names]\n\"ap-southeast-4 (Melbourne)\" = \"Mel\"\nus-east-1 = Primary\n";
+        let file = parse_config_file(text).unwrap();
+        let config = Config {
+            display_names: file["names"].clone(),
+            ..Config::default()
+        };
+        assert_eq!(config.display_name("ap-southeast-4 (Melbourne)"), "Mel");
+        assert_eq!(config.display_name("us-east-1 (Virginia)"), "Primary");
+    }
+
+    #[test]
+    fn printed_config_reads_back_and_flags_override_the_file() {
+        let path = std::env::temp_dir().join(format!("pong-config-{}.toml", std::process::id()));
+        let parse = |flags: &[&str]| {
+            let config = ["--config", path.to_str().unwrap()];
+            Config::parse(config.iter().chain(flags).map(|arg| arg.to_string())).unwrap()
+        };
+        let text = "theme = \"mono\"\ncolumn_widths = \"30,9\"\n\n[names]\n\"ap-southeast-4 (Melbourne)\" = \"Mel\"\nus-east-1 = \"Primary\"\n";
+        fs::write(&path, text).unwrap();
+        let config = parse(&["--retries", "5"]);
+        let printed = config.effective_toml();
+        assert!(printed.contains("\n# retries = 5\n"), "{}", printed);
+
+        fs::write(&path, &printed).unwrap();
+        let reread = parse(&[]);
+        assert_eq!(reread.display_names, config.display_names);
+        assert_eq!(reread.theme_preset, ThemePreset::Mono);
+        assert_eq!(reread.column_widths, Some(vec![30, 9]));
+        // Flag-only settings are printed commented out, so they don't carry
+        // over.
+        assert_eq!(reread.retries, DEFAULT_RETRIES);
+
+        let flagged = parse(&["--theme", "high-contrast", "--column-widths", "20"]);
+        assert_eq!(flagged.theme_preset, ThemePreset::HighContrast);
+        assert_eq!(flagged.column_widths, Some(vec![20]));
+        assert_eq!(flagged.display_names, config.display_names);
+        fs::remove_file(&path).unwrap();
+    }
+}
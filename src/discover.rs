@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use reqwest::Client;
+
+use crate::json::{self, Value};
+use crate::regions::{self, Region};
+
+/// AWS's endpoint list as published with botocore.
+const ENDPOINTS_URL: &str =
+    "https://raw.githubusercontent.com/boto/botocore/develop/botocore/data/endpoints.json";
+
+/// How long a downloaded list is used before it's fetched again.
+const CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long fetching the list may take before falling back.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Partitions reachable from the public internet. The isolated ones list
+/// regions nobody outside them can ping.
+const PUBLIC_PARTITIONS: [&str; 3] = ["aws", "aws-cn", "aws-us-gov"];
+
+/// Where the downloaded list is kept between runs: under `$XDG_CACHE_HOME`,
+/// or else `~/.cache`.
+fn cache_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(dir.join("pong").join("endpoints.json"))
+}
+
+fn fields(value: Option<&Value>) -> &[(String, Value)] {
+    match value {
+        Some(Value::Object(fields)) => fields,
+        _ => &[],
+    }
+}
+
+/// The hostname template in a `defaults` object, if it has one.
+fn hostname(defaults: Option<&Value>) -> Option<&str> {
+    defaults?.get("hostname")?.as_str()
+}
+
+/// The name of the built-in region with this code, e.g. `us-east-1
+/// (Virginia)` for `us-east-1`, so a region keeps its name, and with it its
+/// `[names]` entry, whichever list it came from.
+fn builtin_name(code: &str) -> Option<&'static str> {
+    regions::REGIONS_LIST
+        .iter()
+        .map(|&(name, _)| name)
+        .find(|&name| regions::code(name) == code)
+}
+
+/// A name in the built-in style for a region the built-in list lacks, from
+/// its description: "Mexico (Central)" makes `mx-central-1 (Central)`.
+fn new_name(code: &str, region: &Value) -> &'static str {
+    let description = region.get("description").and_then(Value::as_str);
+    let location = description.map(|description| {
+        description
+            .split_once('(')
+            .and_then(|(_, rest)| rest.strip_suffix(')'))
+            .unwrap_or(description)
+    });
+    let name = match location {
+        Some(location) => format!("{} ({})", code, location),
+        None => code.to_string(),
+    };
+    // Like custom regions, names live for the whole run.
+    Box::leak(name.into_boxed_str())
+}
+
+/// The public regions offering `service` in an `endpoints.json` document.
+/// Regions in the built-in list keep their built-in names; others are named
+/// the same way, e.g. `mx-central-1 (Central)`. FIPS and
+/// other variant endpoints, and deprecated ones, are left out. `path`
+/// replaces each endpoint's path as with `--path`.
+pub fn parse(text: &str, service: &str, path: Option<&str>) -> Result<Vec<Region>, String> {
+    let document = json::parse(text)?;
+    let Some(Value::Array(partitions)) = document.get("partitions") else {
+        return Err("missing partitions".to_string());
+    };
+    // DynamoDB answers on /ping, like the built-in regions; other services
+    // are pinged at their root.
+    let default_path = if service == "dynamodb" { "/ping" } else { "/" };
+
+    let mut found = Vec::new();
+    for partition in partitions {
+        let name = partition.get("partition").and_then(Value::as_str);
+        if !name.is_some_and(|name| PUBLIC_PARTITIONS.contains(&name)) {
+            continue;
+        }
+        let dns_suffix = partition
+            .get("dnsSuffix")
+            .and_then(Value::as_str)
+            .ok_or("partition without a dnsSuffix")?;
+        let Some(service_entry) = partition.get("services").and_then(|s| s.get(service)) else {
+            continue;
+        };
+        let default_hostname = hostname(service_entry.get("defaults"))
+            .or_else(|| hostname(partition.get("defaults")))
+            .unwrap_or("{service}.{region}.{dnsSuffix}");
+        let descriptions = fields(partition.get("regions"));
+
+        for (code, endpoint) in fields(service_entry.get("endpoints")) {
+            let Some((_, region)) = descriptions.iter().find(|(name, _)| name == code) else {
+                continue;
+            };
+            if endpoint.get("deprecated") == Some(&Value::Bool(true)) {
+                continue;
+            }
+            let host = endpoint
+                .get("hostname")
+                .and_then(Value::as_str)
+                .unwrap_or(default_hostname)
+                .replace("{service}", service)
+                .replace("{region}", code)
+                .replace("{dnsSuffix}", dns_suffix);
+            let url = format!("https://{}{}", host, default_path);
+            found.push(Region {
+                name: builtin_name(code).unwrap_or_else(|| new_name(code, region)),
+                targets: vec![regions::with_path(&url, path)?],
+            });
+        }
+    }
+    if found.is_empty() {
+        return Err(format!("no public regions offer '{}'", service));
+    }
+    Ok(found)
+}
+
+async fn fetch() -> Result<String, String> {
+    let response = Client::new()
+        .get(ENDPOINTS_URL)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+    response.text().await.map_err(|err| err.to_string())
+}
+
+/// The regions offering `service`, from the cached endpoint list while it's
+/// under a day old and otherwise from a fresh download, which is cached in
+/// turn. When the download fails, a stale cache is used, and without one the
+/// built-in regions are, which only makes sense for DynamoDB.
+pub async fn regions(service: &str, path: Option<&str>) -> Result<Vec<Region>, String> {
+    let cache = cache_path();
+    let cached = cache.as_ref().and_then(|cache| {
+        let age = fs::metadata(cache)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        fs::read_to_string(cache).ok().map(|text| (text, age))
+    });
+    if let Some((text, Some(age))) = &cached {
+        if *age < CACHE_MAX_AGE {
+            log::info!("using the endpoint list cached {:?} ago", age);
+            return parse(text, service, path);
+        }
+    }
+
+    match fetch().await {
+        Ok(text) => {
+            // Only a list that parses is worth keeping.
+            let regions = parse(&text, service, path)?;
+            if let Some(cache) = &cache {
+                let written = cache
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::write(cache, &text));
+                if let Err(err) = written {
+                    log::warn!("failed to cache {}: {}", cache.display(), err);
+                }
+            }
+            Ok(regions)
+        }
+        Err(err) => {
+            eprintln!("warning: failed to fetch the AWS endpoint list: {}", err);
+            match cached {
+                Some((text, _)) => {
+                    eprintln!("warning: using the cached list, which may be out of date");
+                    parse(&text, service, path)
+                }
+                None if service == "dynamodb" => {
+                    eprintln!("warning: using the built-in regions");
+                    regions::builtin(path)
+                }
+                None => Err(format!("can't discover regions for '{}' offline", service)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENDPOINTS: &str = r#"{"partitions":[
+        {"partition":"aws","dnsSuffix":"amazonaws.com",
+         "defaults":{"hostname":"{service}.{region}.{dnsSuffix}"},
+         "regions":{"us-east-1":{"description":"US East (N. Virginia)"},
+                    "mx-central-1":{"description":"Mexico (Central)"},
+                    "il-central-1":{"description":"Israel (Tel Aviv)"}},
+         "services":{"dynamodb":{"endpoints":{
+            "us-east-1":{},
+            "us-east-1-fips":{"hostname":"dynamodb-fips.us-east-1.amazonaws.com"},
+            "mx-central-1":{},
+            "il-central-1":{"deprecated":true},
+            "local":{"hostname":"localhost:8000"}}}}},
+        {"partition":"aws-cn","dnsSuffix":"amazonaws.com.cn",
+         "regions":{"cn-north-1":{"description":"China (Beijing)"}},
+         "services":{"dynamodb":{"endpoints":{"cn-north-1":{}}},
+                     "s3":{"endpoints":{"cn-north-1":{}}}}},
+        {"partition":"aws-iso","dnsSuffix":"c2s.ic.gov",
+         "regions":{"us-iso-east-1":{"description":"US ISO East"}},
+         "services":{"dynamodb":{"endpoints":{"us-iso-east-1":{}}}}}
+    ]}"#;
+
+    #[test]
+    fn discovers_public_regions_of_a_service() {
+        let regions = parse(ENDPOINTS, "dynamodb", None).unwrap();
+        let found: Vec<(&str, &str)> = regions
+            .iter()
+            .map(|region| (region.name, region.targets[0].url.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "us-east-1 (Virginia)",
+                    "https://dynamodb.us-east-1.amazonaws.com/ping"
+                ),
+                (
+                    "mx-central-1 (Central)",
+                    "https://dynamodb.mx-central-1.amazonaws.com/ping"
+                ),
+                (
+                    "cn-north-1 (Beijing)",
+                    "https://dynamodb.cn-north-1.amazonaws.com.cn/ping"
+                ),
+            ]
+        );
+
+        let s3 = parse(ENDPOINTS, "s3", Some("/health")).unwrap();
+        assert_eq!(
+            s3[0].targets[0].url.as_str(),
+            "https://s3.cn-north-1.amazonaws.com.cn/health"
+        );
+        assert!(parse(ENDPOINTS, "nope", None).is_err());
+    }
+}
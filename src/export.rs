@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::json::{self, Value};
+use crate::ping::Probe;
+use crate::stats::PingStats;
+use crate::ui::{column_labels, display_order, format_elapsed, latency_texts};
+
+/// Unit of the latencies in the machine-readable exports: `--json`,
+/// `--ndjson`, `--record`, `--serve` and `--snapshot-dir`. They carry plain
+/// numbers at full precision; the table and the Markdown report keep their
+/// own formatting in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportUnit {
+    /// Milliseconds, under keys ending in `_ms`.
+    #[default]
+    Ms,
+    /// Seconds, under keys ending in `_s`.
+    S,
+}
+
+impl ExportUnit {
+    fn suffix(self) -> &'static str {
+        match self {
+            ExportUnit::Ms => "ms",
+            ExportUnit::S => "s",
+        }
+    }
+
+    /// The key a latency is exported under, e.g. `avg_ms`.
+    fn key(self, name: &str) -> String {
+        format!("{}_{}", name, self.suffix())
+    }
+
+    /// A latency in milliseconds as a JSON number in this unit.
+    fn number(self, ms: Option<f64>) -> String {
+        match self {
+            ExportUnit::Ms => json::number(ms),
+            ExportUnit::S => json::number(ms.map(|ms| ms / 1000.0)),
+        }
+    }
+
+    /// Reads the latency `name` from an exported object in either unit, as
+    /// milliseconds. It's `None` when missing or `null`.
+    pub fn read(object: &Value, name: &str) -> Result<Option<f64>, String> {
+        for unit in [ExportUnit::Ms, ExportUnit::S] {
+            let key = unit.key(name);
+            match object.get(&key) {
+                Some(Value::Number(value)) if unit == ExportUnit::S => {
+                    return Ok(Some(value * 1000.0))
+                }
+                Some(Value::Number(value)) => return Ok(Some(*value)),
+                Some(Value::Null) | None => {}
+                Some(_) => return Err(format!("{} must be a number or null", key)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl FromStr for ExportUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ms" => Ok(ExportUnit::Ms),
+            "s" => Ok(ExportUnit::S),
+            _ => Err(format!("invalid export unit '{}', expected ms or s", s)),
+        }
+    }
+}
+
+/// Renders the final stats as a GitHub-flavored Markdown table, sorted the same
+/// way as the TUI, with the sample count and run duration as a caption. Like
+/// the table, it shows regions under their `[names]`, while the
+/// machine-readable exports keep the real names.
+pub fn markdown_table(stats: &[PingStats], config: &Config, elapsed: Duration) -> String {
+    let labels = column_labels(&config.columns);
+    let mut out = String::new();
+
+    out.push_str(&format!("| {} |\n", labels.join(" | ")));
+    out.push_str("| --- |");
+    out.push_str(&" ---: |".repeat(labels.len() - 1));
+    out.push('\n');
+
+    for i in display_order(stats, &HashSet::new(), config) {
+        let stat = &stats[i];
+        let cells: Vec<String> = latency_texts(stat, config)
+            .into_iter()
+            .chain(
+                config
+                    .columns
+                    .iter()
+                    .map(|column| column.text(stat, config)),
+            )
+            .collect();
+        out.push_str(&format!(
+            "| {} | {} |\n",
+            config.display_name(stat.region),
+            cells.join(" | ")
+        ));
+    }
+
+    let samples: u64 = stats.iter().map(|stat| stat.samples).sum();
+    out.push_str(&format!(
+        "\n_{} samples over {}._\n",
+        samples,
+        format_elapsed(elapsed)
+    ));
+
+    out
+}
+
+pub fn write_markdown(
+    path: &Path,
+    stats: &[PingStats],
+    config: &Config,
+    elapsed: Duration,
+) -> io::Result<()> {
+    fs::write(path, markdown_table(stats, config, elapsed))
+}
+
+/// Renders the final stats as JSON, one object per region in table order.
+/// Latencies are in the `--export-unit` and loss is a fraction; `null` marks
+/// values a region doesn't have yet.
+pub fn json_report(stats: &[PingStats], config: &Config, elapsed: Duration) -> String {
+    let unit = config.export_unit;
+    let regions: Vec<String> = display_order(stats, &HashSet::new(), config)
+        .into_iter()
+        .map(|i| {
+            let stat = &stats[i];
+            let latencies = [
+                ("last", stat.last()),
+                ("min", stat.min()),
+                ("avg", stat.avg()),
+                ("max", stat.max()),
+                ("stddev", stat.stddev()),
+                ("p95", stat.p95()),
+                ("p99", stat.p99()),
+            ];
+            let latencies: Vec<String> = latencies
+                .iter()
+                .map(|&(name, ms)| format!(r#""{}":{}"#, unit.key(name), unit.number(ms)))
+                .collect();
+            format!(
+                r#"{{"region":{},"samples":{},{},"loss":{},"discarded":{}}}"#,
+                json::string(stat.region),
+                stat.samples,
+                latencies.join(","),
+                json::number(stat.loss()),
+                stat.discarded()
+            )
+        })
+        .collect();
+    format!(
+        "{{\"duration_secs\":{},\"regions\":[{}]}}\n",
+        elapsed.as_secs_f64(),
+        regions.join(",")
+    )
+}
+
+pub fn write_json(
+    path: &Path,
+    stats: &[PingStats],
+    config: &Config,
+    elapsed: Duration,
+) -> io::Result<()> {
+    fs::write(path, json_report(stats, config, elapsed))
+}
+
+/// Reads the average latency of each region from a `--json` report, for
+/// comparing a run against it with `--baseline`.
+pub fn load_baseline(path: &Path) -> Result<HashMap<String, Option<f64>>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let report = json::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let Some(Value::Array(regions)) = report.get("regions") else {
+        return Err(format!("{}: expected a \"regions\" array", path.display()));
+    };
+    regions
+        .iter()
+        .map(|region| {
+            let name = region
+                .get("region")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("{}: region without a name", path.display()))?;
+            let avg = ExportUnit::read(region, "avg")
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            Ok((name.to_string(), avg))
+        })
+        .collect()
+}
+
+/// Formats a sample just recorded in `stat` as one `--ndjson` line. Failed
+/// pings have a `null` latency and an `error`; `bytes` is the response size
+/// when it was measured, and `loss` covers the region's recent window.
+/// The latency is in `unit`, and `--replay` reads these lines back in either.
+pub fn ndjson_line(stat: &PingStats, probe: Probe, time: SystemTime, unit: ExportUnit) -> String {
+    let mut extra = String::new();
+    if let Err(err) = probe.result {
+        extra.push_str(&format!(r#","error":{}"#, json::string(err.name())));
+    }
+    if let Some(bytes) = probe.bytes {
+        extra.push_str(&format!(r#","bytes":{}"#, bytes));
+    }
+    format!(
+        r#"{{"timestamp":{},"region":{},"{}":{}{},"retries":{},"loss":{}}}"#,
+        json::string(&json::timestamp(time)),
+        json::string(stat.region),
+        unit.key("latency"),
+        unit.number(
+            probe
+                .result
+                .ok()
+                .map(|latency| latency.as_secs_f64() * 1000.0)
+        ),
+        extra,
+        probe.retries,
+        json::number(stat.loss())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsOptions;
+
+    #[test]
+    fn json_report_exports_plain_numbers_in_the_chosen_unit() {
+        let mut stat = PingStats::new("eu-west-1", StatsOptions::default());
+        stat.add_latency(Some(Duration::from_micros(12_500)));
+        let config = Config {
+            export_unit: ExportUnit::S,
+            ..Config::default()
+        };
+
+        let text = json_report(&[stat], &config, Duration::from_secs(1));
+        assert!(text.contains(r#""avg_s":0.0125,"#), "{}", text);
+        let report = json::parse(&text).unwrap();
+        let Some(Value::Array(regions)) = report.get("regions") else {
+            panic!("no regions in {}", text);
+        };
+        assert_eq!(ExportUnit::read(&regions[0], "avg"), Ok(Some(12.5)));
+        assert_eq!(ExportUnit::read(&regions[0], "p99"), Ok(Some(12.5)));
+    }
+
+    #[test]
+    fn markdown_shows_display_names_and_json_keeps_real_ones() {
+        let mut stat = PingStats::new("eu-west-1 (Ireland)", StatsOptions::default());
+        stat.add_latency(Some(Duration::from_millis(20)));
+        let mut config = Config::default();
+        config
+            .display_names
+            .insert("eu-west-1".to_string(), "Dublin".to_string());
+        let stats = [stat];
+
+        let markdown = markdown_table(&stats, &config, Duration::from_secs(1));
+        assert!(markdown.contains("| Dublin | "), "{}", markdown);
+        let report = json_report(&stats, &config, Duration::from_secs(1));
+        assert!(
+            report.contains(r#""region":"eu-west-1 (Ireland)""#),
+            "{}",
+            report
+        );
+        assert!(!report.contains("Dublin"), "{}", report);
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Most log output held back while the TUI is up; the oldest lines go first.
+const MAX_BUFFERED: usize = 1024 * 1024;
+
+/// Log output held back while the TUI owns the terminal, since lines written
+/// to stderr would land on top of the table.
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<u8>>>);
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        bytes.extend(buf);
+        if bytes.len() > MAX_BUFFERED {
+            // Drop whole lines so the dump doesn't start halfway through one.
+            let excess = bytes.len() - MAX_BUFFERED;
+            let cut = bytes
+                .iter()
+                .skip(excess)
+                .position(|&b| b == b'\n')
+                .map_or(bytes.len(), |i| excess + i + 1);
+            bytes.drain(..cut);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl LogBuffer {
+    /// Writes out everything held back so far, once the terminal is restored.
+    pub fn dump(&self) -> io::Result<()> {
+        let bytes = std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        let (front, back) = bytes.as_slices();
+        let mut err = io::stderr().lock();
+        err.write_all(front)?;
+        err.write_all(back)?;
+        err.flush()
+    }
+}
+
+/// Sets up logging to stderr, filtered by `RUST_LOG`. When the TUI will be
+/// drawn on the same terminal, the output is buffered instead, and the buffer
+/// returned to be dumped once the TUI exits.
+pub fn init(tui: bool) -> Option<LogBuffer> {
+    let mut builder = env_logger::Builder::from_default_env();
+    let buffer = (tui && io::stderr().is_terminal()).then(LogBuffer::default);
+    if let Some(buffer) = &buffer {
+        builder.target(env_logger::Target::Pipe(Box::new(buffer.clone())));
+    }
+    builder.init();
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_drops_the_oldest_whole_lines() {
+        let mut buffer = LogBuffer::default();
+        let line = "x".repeat(MAX_BUFFERED / 2 - 1) + "\n";
+        for _ in 0..3 {
+            buffer.write_all(line.as_bytes()).unwrap();
+        }
+        buffer.write_all(b"last\n").unwrap();
+
+        let bytes = buffer.0.lock().unwrap();
+        assert_eq!(bytes.len(), line.len() + "last\n".len());
+        assert_eq!(bytes.front(), Some(&b'x'));
+    }
+}
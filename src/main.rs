@@ -1,290 +1,649 @@
+mod checkpoint;
+mod config;
+mod discover;
+mod export;
+mod json;
+mod logging;
+mod otlp;
+mod ping;
+mod preflight;
+mod prober;
+mod recommend;
+mod records;
 mod regions;
+mod replay;
+mod resolver;
+mod screenshot;
+mod serve;
+mod shutdown;
+mod snapshots;
+mod stats;
+mod storm;
+mod theme;
+mod ui;
+mod warmup;
 
-use arraydeque::{ArrayDeque, Wrapping};
+use config::{Config, ProbeMode};
 use crossterm::{
+    cursor,
     event::{self, Event, KeyCode},
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Layout},
-    style::{Color, Style},
-    text::Span,
-    widgets::{Block, Borders, Cell, Row, Table},
-    Terminal,
-};
-use regions::REGIONS_LIST;
+use futures::future::join_all;
+use ping::{probe_endpoints, Probe};
+use prober::{HttpProber, IcmpProber, Prober, QuicProber, TcpProber};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use regions::Region;
 use reqwest::Client;
-use statrs::statistics::{Data, OrderStatistics, Statistics};
-use std::time::{Duration, Instant};
-use std::{io::stdout, sync::Arc};
+use resolver::CachingResolver;
+use shutdown::Shutdown;
+use stats::PingStats;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, stdout, BufWriter, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use storm::Storm;
 use tokio::{
-    sync::{mpsc, Mutex},
+    net::TcpListener,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        Mutex, Notify,
+    },
     task::JoinHandle,
     time::sleep,
 };
 
+/// Regions whose coefficient of variation stays below this are polled
+/// progressively less often in `--adaptive` mode.
+const ADAPTIVE_STABLE_CV: f64 = 0.05;
+/// Above this, an adaptive region snaps back to the minimum interval.
+const ADAPTIVE_VOLATILE_CV: f64 = 0.2;
+
+fn next_interval(current: Duration, stats: &PingStats, config: &Config) -> Duration {
+    let next = match stats.cv() {
+        Some(cv) if cv > ADAPTIVE_VOLATILE_CV => config.min_interval,
+        Some(cv) if cv < ADAPTIVE_STABLE_CV => current.mul_f64(1.5),
+        _ => current,
+    };
+    next.clamp(config.min_interval, config.max_interval)
+}
+
+/// How often regions hidden by `--hide-unreachable` are still pinged.
+const UNREACHABLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `--sonar` beeps once per this many milliseconds of the selected region's
+/// latency, so faster beeps mean a faster region.
+const SONAR_SCALE: u32 = 10;
+/// Bounds on the time between sonar beeps, so low latencies don't turn into a
+/// buzz and high ones still beep now and then.
+const SONAR_MIN_PERIOD: Duration = Duration::from_millis(300);
+const SONAR_MAX_PERIOD: Duration = Duration::from_secs(5);
+
+/// Exit statuses of a `--run-for`, `--markdown` or `--json` run in which some
+/// or all regions never answered. 1 and 2 are taken by runtime and usage
+/// errors.
+const EXIT_SOME_FAILED: u8 = 3;
+const EXIT_ALL_FAILED: u8 = 4;
+
+/// How far back the footer's sample rate looks.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Samples the channel from the ping tasks holds per region before further
+/// ones are dropped, and the least it holds however few regions there are.
+const CHANNEL_PER_REGION: usize = 4;
+const MIN_CHANNEL_CAPACITY: usize = 32;
+
+/// How long a flashed message stays in the footer.
+const FLASH_DURATION: Duration = Duration::from_secs(3);
+
+/// Where the ping tasks send their samples.
 #[derive(Clone)]
-struct PingStats<'a> {
-    region: &'a str,
-    latencies: ArrayDeque<f64, 100, Wrapping>,
+struct SampleSender {
+    tx: mpsc::Sender<(&'static str, Probe)>,
+    /// Samples dropped because the channel was full.
+    dropped: Arc<AtomicU64>,
 }
 
-impl<'a> PingStats<'a> {
-    fn new(region: &'a str) -> Self {
-        PingStats {
-            region,
-            latencies: ArrayDeque::new(),
+impl SampleSender {
+    /// Sends a sample of `region`, returning `false` once nothing receives
+    /// them anymore. Waiting for room would delay the next probe and skew
+    /// its timing, so a sample that doesn't fit is dropped and counted.
+    fn send(&self, region: &'static str, probe: Probe) -> bool {
+        match self.tx.try_send((region, probe)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, atomic::Ordering::Relaxed);
+                log::debug!("{}: dropped a sample, the channel is full", region);
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
         }
     }
+}
 
-    fn add_latency(&mut self, latency: Option<Duration>) {
-        if let Some(lat) = latency {
-            self.latencies.push_back(lat.as_secs_f64() * 1000.0);
-        }
-    }
+/// Pings `region` until the receiving end of `samples` goes away. The first
+/// ping waits for `first_delay`, which `--stagger` sets.
+async fn fetch_latency_for_region(
+    prober: Arc<dyn Prober>,
+    Region {
+        name: region,
+        targets,
+    }: Region,
+    config: Arc<Config>,
+    probe_now: Arc<Notify>,
+    storm: Arc<Storm>,
+    samples: SampleSender,
+    first_delay: Duration,
+) {
+    // The task keeps its own copy of the region's samples to drive the
+    // adaptive interval without contending for the shared stats.
+    let mut recent = PingStats::new(region, config.stats);
+    let mut interval = config.min_interval;
 
-    fn min(&self) -> Option<f64> {
-        self.latencies.iter().copied().reduce(f64::min)
+    if !first_delay.is_zero() {
+        tokio::select! {
+            _ = sleep(first_delay) => {}
+            _ = probe_now.notified() => {}
+        }
     }
+    loop {
+        let mut failed = false;
+        let storming = storm.raging(Instant::now());
+        let rounds = if storming { storm::CONCURRENCY } else { 1 };
+        let probes = join_all((0..rounds).map(|_| {
+            probe_endpoints(
+                prober.as_ref(),
+                &targets,
+                config.probe_strategy,
+                config.retries,
+            )
+        }))
+        .await;
+        for probe in probes.into_iter().flatten() {
+            if let Err(err) = probe.result {
+                log::info!(
+                    "{}: probe failed ({}) after {} retries",
+                    region,
+                    err.name(),
+                    probe.retries
+                );
+            }
+            failed |= probe.result.is_err();
+            recent.add_probe(probe);
+            if !samples.send(region, probe) {
+                log::debug!("{}: stopping, the receiver is gone", region);
+                return;
+            }
+        }
 
-    fn max(&self) -> Option<f64> {
-        self.latencies.iter().copied().reduce(f64::max)
-    }
+        if config.adaptive {
+            let next = if failed {
+                config.min_interval
+            } else {
+                next_interval(interval, &recent, &config)
+            };
+            if next != interval {
+                log::debug!("{}: interval now {:?}", region, next);
+            }
+            interval = next;
+        }
 
-    fn avg(&self) -> Option<f64> {
-        if self.latencies.is_empty() {
-            None
+        let wait = if storming {
+            storm::INTERVAL
+        } else if config.hide_unreachable && recent.is_unreachable() {
+            // Keep checking hidden regions, slowly, so they reappear if
+            // connectivity changes.
+            interval.max(UNREACHABLE_INTERVAL)
+        } else if let Some(fast_retry) = config.fast_retry.filter(|_| failed) {
+            // Probe a failing region quickly so its recovery shows up sooner.
+            fast_retry
         } else {
-            Some(self.latencies.iter().copied().mean())
+            interval
+        };
+
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = probe_now.notified() => {}
         }
     }
+}
 
-    fn stddev(&self) -> Option<f64> {
-        if self.latencies.len() > 1 {
-            Some(self.latencies.iter().copied().std_dev())
-        } else {
-            None
+/// Spawns a ping task per region. Notifying a region's entry in `probe_now`
+/// makes its task skip the rest of its wait and ping immediately.
+async fn start_fetching_latencies(
+    prober: Arc<dyn Prober>,
+    config: Arc<Config>,
+    probe_now: &HashMap<&'static str, Arc<Notify>>,
+    storm: &Arc<Storm>,
+    tx: mpsc::Sender<(&'static str, Probe)>,
+    dropped: &Arc<AtomicU64>,
+) -> Vec<JoinHandle<()>> {
+    log::info!(
+        "probing {} regions in {:?} mode",
+        config.regions.len(),
+        config.mode
+    );
+    let samples = SampleSender {
+        tx,
+        dropped: Arc::clone(dropped),
+    };
+    config
+        .regions
+        .iter()
+        .enumerate()
+        .map(|(index, region)| {
+            log::debug!(
+                "starting probes for {} at {}",
+                region.name,
+                region
+                    .targets
+                    .iter()
+                    .map(|target| target.url.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            tokio::spawn(fetch_latency_for_region(
+                Arc::clone(&prober),
+                region.clone(),
+                Arc::clone(&config),
+                Arc::clone(&probe_now[region.name]),
+                Arc::clone(storm),
+                samples.clone(),
+                config.stagger * index as u32,
+            ))
+        })
+        .collect()
+}
+
+struct App {
+    pinned: HashSet<&'static str>,
+    selected: Option<&'static str>,
+    started: Instant,
+    show_histogram: bool,
+    /// Index in the display order of the first row on screen.
+    scroll: usize,
+    /// How many regions fit on screen, as of the last draw.
+    page_rows: usize,
+    /// Region order captured when the sort was frozen. Values keep updating
+    /// but rows hold their positions until the order is refreshed.
+    frozen_order: Option<Vec<&'static str>>,
+    /// Regions marked for the comparison panel, oldest first; the panel shows
+    /// once there are two.
+    compared: Vec<&'static str>,
+    /// Message shown in place of the footer's hint, and when it was set.
+    flash: Option<(String, Instant)>,
+    /// Whether `--sonar` beeps are on; toggled with `b`.
+    sonar: bool,
+    last_beep: Instant,
+    /// The recommended region, kept steady between near ties.
+    leader: recommend::Leader,
+    /// Samples received so far, failed ones included.
+    received: u64,
+    /// `received` as of recent draws, for the footer's sample rate.
+    received_history: VecDeque<(Instant, u64)>,
+    /// Samples the ping tasks dropped because the channel was full.
+    dropped: Arc<AtomicU64>,
+    /// Whether the key bindings overlay is open.
+    show_help: bool,
+    /// Whether `--warmup` is still hiding the table.
+    phase: warmup::Phase,
+    /// The burst of extra probes started by `--storm` or the `S` key, shared
+    /// with the ping tasks.
+    storm: Arc<Storm>,
+}
+
+impl App {
+    fn new() -> Self {
+        App {
+            pinned: HashSet::new(),
+            selected: None,
+            started: Instant::now(),
+            show_histogram: false,
+            scroll: 0,
+            page_rows: 1,
+            frozen_order: None,
+            compared: Vec::new(),
+            flash: None,
+            sonar: false,
+            last_beep: Instant::now(),
+            leader: recommend::Leader::default(),
+            received: 0,
+            received_history: VecDeque::new(),
+            dropped: Arc::new(AtomicU64::new(0)),
+            show_help: false,
+            phase: warmup::Phase::Running,
+            storm: Arc::new(Storm::new(storm::DEFAULT_DURATION, None)),
         }
     }
 
-    fn last(&self) -> Option<f64> {
-        self.latencies.iter().last().copied()
+    /// The flashed footer message, until it expires.
+    fn flash(&self) -> Option<&str> {
+        self.flash
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < FLASH_DURATION)
+            .map(|(message, _)| message.as_str())
     }
 
-    fn p95(&self) -> Option<f64> {
-        if self.latencies.is_empty() {
-            None
-        } else {
-            let mut data = Data::new(self.latencies.iter().copied().collect::<Vec<_>>());
-            Some(data.percentile(95))
+    /// Indices into `stats` in the order rows are shown: the live sort order,
+    /// or the frozen one when the sort is frozen. Regions missing from the
+    /// frozen order keep their live order after the rest.
+    fn order(&self, stats: &[PingStats], config: &Config) -> Vec<usize> {
+        let mut order = ui::display_order(stats, &self.pinned, config);
+        if let Some(frozen) = &self.frozen_order {
+            order.sort_by_key(|&i| {
+                frozen
+                    .iter()
+                    .position(|&region| region == stats[i].region)
+                    .unwrap_or(usize::MAX)
+            });
         }
+        order
     }
 
-    fn p99(&self) -> Option<f64> {
-        if self.latencies.is_empty() {
-            None
-        } else {
-            let mut data = Data::new(self.latencies.iter().copied().collect::<Vec<_>>());
-            Some(data.percentile(99))
-        }
+    fn capture_order(&mut self, stats: &[PingStats<'static>], config: &Config) {
+        let order = ui::display_order(stats, &self.pinned, config);
+        self.frozen_order = Some(order.into_iter().map(|i| stats[i].region).collect());
     }
-}
 
-fn format_latency_option(value: Option<f64>) -> String {
-    value
-        .map(|v| format!("{:.2} ms", v))
-        .unwrap_or("--".to_string())
-}
+    fn toggle_freeze(&mut self, stats: &[PingStats<'static>], config: &Config) {
+        if self.frozen_order.take().is_none() {
+            self.capture_order(stats, config);
+        }
+    }
 
-async fn ping_region(client: &Client, url: &str) -> Option<Duration> {
-    let start = Instant::now();
-    let result = client
-        .head(url)
-        .timeout(Duration::from_secs(3))
-        .send()
-        .await;
-    match result {
-        Ok(_) => Some(start.elapsed()),
-        Err(_) => None,
+    /// Re-sorts the rows once while keeping the sort frozen.
+    fn refresh_order(&mut self, stats: &[PingStats<'static>], config: &Config) {
+        if self.frozen_order.is_some() {
+            self.capture_order(stats, config);
+        }
     }
-}
 
-async fn fetch_latency_for_region<'a>(
-    client: Client,
-    region: &'a str,
-    url: &'a str,
-    tx: mpsc::Sender<(&'a str, Option<Duration>)>,
-) {
-    loop {
-        let mut retries = 3;
-        let mut latency;
+    fn move_selection(&mut self, stats: &[PingStats<'static>], config: &Config, delta: isize) {
+        let order = self.order(stats, config);
+        if order.is_empty() {
+            return;
+        }
+        let current = self
+            .selected
+            .and_then(|region| order.iter().position(|&i| stats[i].region == region));
+        // Without a selection, moving down starts just above the first row.
+        let from = current.map_or(-1, |pos| pos as isize);
+        let next = from
+            .saturating_add(delta)
+            .clamp(0, order.len() as isize - 1) as usize;
+        self.selected = Some(stats[order[next]].region);
+    }
 
-        loop {
-            latency = ping_region(&client, url).await;
-            if latency.is_some() || retries == 0 {
-                break;
+    fn toggle_pin(&mut self) {
+        if let Some(region) = self.selected {
+            if !self.pinned.remove(region) {
+                self.pinned.insert(region);
             }
-            retries -= 1;
-            sleep(Duration::from_millis(500)).await;
         }
+    }
 
-        if tx.send((region, latency)).await.is_err() {
-            break; // Stop if the channel is closed
+    /// Whether a sonar beep is due for the selected region: once its last
+    /// latency times `SONAR_SCALE` has passed since the previous beep.
+    fn sonar_due(&mut self, stats: &[PingStats]) -> bool {
+        let last = self
+            .selected
+            .and_then(|region| stats.iter().find(|stat| stat.region == region))
+            .and_then(PingStats::last);
+        let Some(ms) = last.filter(|_| self.sonar) else {
+            return false;
+        };
+        let period = Duration::from_secs_f64(ms / 1000.0 * f64::from(SONAR_SCALE))
+            .clamp(SONAR_MIN_PERIOD, SONAR_MAX_PERIOD);
+        if self.last_beep.elapsed() < period {
+            return false;
         }
+        self.last_beep = Instant::now();
+        true
+    }
 
-        sleep(Duration::from_secs(1)).await;
+    /// Samples received per second over the last `RATE_WINDOW`, which
+    /// smooths out the bursts of regions answering at once. `None` until
+    /// there's a second of history.
+    fn sample_rate(&mut self) -> Option<f64> {
+        let now = Instant::now();
+        self.received_history.push_back((now, self.received));
+        while self
+            .received_history
+            .front()
+            .is_some_and(|&(at, _)| now.duration_since(at) > RATE_WINDOW)
+        {
+            self.received_history.pop_front();
+        }
+        let &(since, from) = self.received_history.front()?;
+        let span = now.duration_since(since);
+        (span >= Duration::from_secs(1)).then(|| (self.received - from) as f64 / span.as_secs_f64())
     }
-}
 
-async fn start_fetching_latencies(
-    client: Client,
-    tx: mpsc::Sender<(&'static str, Option<Duration>)>,
-) -> Vec<JoinHandle<()>> {
-    REGIONS_LIST
-        .iter()
-        .map(|(region, url)| {
-            let client_clone = client.clone();
-            let tx_clone = tx.clone();
-            tokio::spawn(fetch_latency_for_region(
-                client_clone,
-                region,
-                url,
-                tx_clone,
-            ))
-        })
-        .collect()
+    /// Marks the selected region for comparison, or unmarks it. Marking a
+    /// third region replaces the one marked first.
+    fn toggle_compare(&mut self) {
+        let Some(region) = self.selected else {
+            return;
+        };
+        if let Some(index) = self.compared.iter().position(|&r| r == region) {
+            self.compared.remove(index);
+        } else {
+            if self.compared.len() == 2 {
+                self.compared.remove(0);
+            }
+            self.compared.push(region);
+        }
+    }
 }
 
 async fn render_ui(
     terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
     stats: Arc<Mutex<Vec<PingStats<'_>>>>,
+    app: &mut App,
+    config: &Config,
+    now: Instant,
 ) {
+    if let Some(remaining) = app.phase.remaining(now) {
+        terminal
+            .draw(|f| ui::draw_warmup(f, remaining, config))
+            .unwrap();
+        return;
+    }
     let stats_copy: Vec<_> = {
         let stats = stats.lock().await;
         stats.clone()
     };
 
     terminal
-        .draw(|f| {
-            let chunks = Layout::default()
-                .constraints([Constraint::Percentage(100)].as_ref())
-                .split(f.area());
-
-            let mut sorted_stats: Vec<_> = stats_copy.iter().collect();
-            sorted_stats.sort_by(|a, b| {
-                a.avg()
-                    .partial_cmp(&b.avg())
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            });
+        .draw(|f| ui::draw_table(f, &stats_copy, app, config))
+        .unwrap();
+}
 
-            let rows: Vec<Row> = sorted_stats
-                .iter()
-                .map(|stat| {
-                    let last_text = format_latency_option(stat.last());
-                    let avg_text = format_latency_option(stat.avg());
-                    let min_text = format_latency_option(stat.min());
-                    let max_text = format_latency_option(stat.max());
-                    let stddev_text = format_latency_option(stat.stddev());
-                    let p95_text = format_latency_option(stat.p95());
-                    let p99_text = format_latency_option(stat.p99());
-
-                    let last_value = stat.last();
-                    let avg_value = stat.avg();
-
-                    let last_style = if let (Some(last), Some(avg)) = (last_value, avg_value) {
-                        if last > avg {
-                            Style::default().fg(Color::Red) // Worse performance
-                        } else {
-                            Style::default().fg(Color::Green) // Better performance
-                        }
-                    } else {
-                        Style::default().fg(Color::Yellow)
-                    };
-
-                    Row::new(vec![
-                        Cell::from(Span::styled(stat.region, Style::default().fg(Color::White))),
-                        Cell::from(Span::styled(last_text, last_style)),
-                        Cell::from(Span::styled(min_text, Style::default().fg(Color::Yellow))),
-                        Cell::from(Span::styled(avg_text, Style::default().fg(Color::Yellow))),
-                        Cell::from(Span::styled(max_text, Style::default().fg(Color::Yellow))),
-                        Cell::from(Span::styled(
-                            stddev_text,
-                            Style::default().fg(Color::Yellow),
-                        )),
-                        Cell::from(Span::styled(p95_text, Style::default().fg(Color::Yellow))),
-                        Cell::from(Span::styled(p99_text, Style::default().fg(Color::Yellow))),
-                    ])
-                })
-                .collect();
-
-            let widths = [
-                Constraint::Percentage(20),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-            ];
-
-            let table = Table::new(rows, &widths)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title("Ping Latencies"),
-                )
-                .header(
-                    Row::new(vec![
-                        Cell::from("AWS Region"),
-                        Cell::from("Last"),
-                        Cell::from("Min"),
-                        Cell::from("Avg"),
-                        Cell::from("Max"),
-                        Cell::from("Stddev"),
-                        Cell::from("P95"),
-                        Cell::from("P99"),
-                    ])
-                    .style(Style::default().fg(Color::Cyan)),
-                );
+/// How often the `--record` file is flushed while running.
+const RECORD_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
-            f.render_widget(table, chunks[0]);
-        })
-        .unwrap();
+/// Handles each sample as it arrives from the ping tasks.
+struct Recorder {
+    /// Whether the latest attempt of every region failed.
+    offline: bool,
+    /// The `--record` capture, if any.
+    capture: Option<BufWriter<File>>,
+    flushed: Instant,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
+impl Recorder {
+    fn new(capture: Option<File>) -> Self {
+        Recorder {
+            offline: false,
+            capture: capture.map(BufWriter::new),
+            flushed: Instant::now(),
+        }
+    }
 
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    /// Adds a sample to its region's stats and, with `--ndjson`, streams it to
+    /// stdout. The line is flushed right away so consumers see every tick as
+    /// it happens. The `--record` capture gets the same line but is only
+    /// flushed every `RECORD_FLUSH_INTERVAL`.
+    async fn record(
+        &mut self,
+        stats: &Mutex<Vec<PingStats<'_>>>,
+        region: &str,
+        probe: Probe,
+        config: &Config,
+    ) -> io::Result<()> {
+        let mut stats = stats.lock().await;
+        if let Some(stat) = stats.iter_mut().find(|stat| stat.region == region) {
+            stat.add_probe(probe);
+            let line = export::ndjson_line(stat, probe, SystemTime::now(), config.export_unit);
+            if let Some(capture) = &mut self.capture {
+                writeln!(capture, "{}", line)?;
+                if self.flushed.elapsed() >= RECORD_FLUSH_INTERVAL {
+                    capture.flush()?;
+                    self.flushed = Instant::now();
+                }
+            }
+            if config.ndjson {
+                let mut out = stdout().lock();
+                writeln!(out, "{}", line)?;
+                out.flush()?;
+            }
+        }
 
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+        if probe.result.is_ok() {
+            // Only the offline-to-online transition rings, not every success.
+            let was_offline = std::mem::take(&mut self.offline);
+            if was_offline {
+                log::info!("connectivity is back, {} answered", region);
+            }
+            if was_offline && config.notify {
+                let mut err = io::stderr().lock();
+                err.write_all(b"\x07")?;
+                err.flush()?;
+            }
+        } else if !self.offline && stats.iter().all(|stat| stat.last_error.is_some()) {
+            log::warn!("every region is failing");
+            self.offline = true;
+        }
+        Ok(())
+    }
 
-    let client = Client::new();
+    /// Writes out whatever the `--record` capture still has buffered.
+    fn finish(&mut self) -> io::Result<()> {
+        match &mut self.capture {
+            Some(capture) => capture.flush(),
+            None => Ok(()),
+        }
+    }
+}
 
-    let stats = Arc::new(Mutex::new(
-        REGIONS_LIST
-            .iter()
-            .map(|(region, _)| PingStats::new(region))
-            .collect(),
-    ));
+/// Resolves at `deadline`, or never without one.
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
 
-    let (tx, mut rx) = mpsc::channel(32);
+/// Acts on what a storm did since the last poll: wakes the ping tasks when it
+/// starts, and reports on it once it has settled. Returns a message for the
+/// footer.
+async fn handle_storm(
+    storm: &Storm,
+    stats: &Mutex<Vec<PingStats<'static>>>,
+    probe_now: &HashMap<&'static str, Arc<Notify>>,
+    config: &Config,
+) -> Option<String> {
+    match storm.poll(Instant::now())? {
+        storm::Event::Started => {
+            log::info!("storm started");
+            probe_now.values().for_each(|notify| notify.notify_one());
+            Some("storm started".to_string())
+        }
+        storm::Event::Settled(window) => {
+            let deltas = window.report(&stats.lock().await);
+            for delta in &deltas {
+                let line = delta.line(config.precision);
+                if config.no_tui {
+                    eprintln!("storm: {}", line);
+                } else {
+                    log::info!("storm: {}", line);
+                }
+            }
+            Some(storm::summary(&deltas, config.precision))
+        }
+    }
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout(), LeaveAlternateScreen, cursor::Show);
+}
 
-    let handles = start_fetching_latencies(client.clone(), tx).await;
+/// Puts the terminal back when dropped, so an error inside the TUI doesn't
+/// leave it in raw mode on the alternate screen. On Windows, leaving raw mode
+/// is also what restores the console mode saved when entering it.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        enable_raw_mode()?;
+        if let Err(err) = execute!(stdout(), EnterAlternateScreen) {
+            restore_terminal();
+            return Err(err);
+        }
+
+        // Panic messages print before unwinding reaches the guard, so restore
+        // the terminal first or they'd be lost with the alternate screen.
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+async fn run_tui(
+    app: &mut App,
+    stats: &Arc<Mutex<Vec<PingStats<'static>>>>,
+    rx: &mut mpsc::Receiver<(&'static str, Probe)>,
+    recorder: &mut Recorder,
+    probe_now: &HashMap<&'static str, Arc<Notify>>,
+    deadline: Option<Instant>,
+    config: &Config,
+) -> io::Result<()> {
+    let _guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(stdout());
+    let mut terminal = Terminal::new(backend)?;
 
     let (event_tx, mut event_rx) = mpsc::channel(1);
-    tokio::spawn(async move {
-        loop {
-            if event::poll(Duration::from_millis(100)).unwrap() {
-                if let Event::Key(key_event) = event::read().unwrap() {
-                    event_tx.send(key_event).await.unwrap();
+    // Reading events blocks, so it gets its own thread. The loop ends once the
+    // TUI drops the receiver, and on a read error it drops the sender, which
+    // the TUI takes as a signal to shut down.
+    let events = tokio::task::spawn_blocking(move || {
+        while !event_tx.is_closed() {
+            match event::poll(Duration::from_millis(100))
+                .and_then(|ready| ready.then(event::read).transpose())
+            {
+                Ok(Some(Event::Key(key_event))) => {
+                    if event_tx.blocking_send(key_event).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    log::error!("failed to read terminal events: {}", err);
+                    return;
                 }
             }
         }
@@ -296,32 +655,516 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     while !exit {
         tokio::select! {
             _ = interval.tick() => {
-                render_ui(&mut terminal, Arc::clone(&stats)).await;
-            }
-            Some((region, latency)) = rx.recv() => {
-                let mut stats = stats.lock().await;
-                if let Some(stat) = stats.iter_mut().find(|stat| stat.region == region) {
-                    stat.add_latency(latency);
+                // One instant for both, so the warm-up can't end between
+                // deciding what to draw and drawing it.
+                let now = Instant::now();
+                if app.phase.advance(now) {
+                    stats.lock().await.iter_mut().for_each(PingStats::reset);
+                }
+                if let Some(message) = handle_storm(&app.storm, stats, probe_now, config).await {
+                    app.flash = Some((message, now));
+                }
+                render_ui(&mut terminal, Arc::clone(stats), app, config, now).await;
+                if app.sonar_due(&stats.lock().await) {
+                    let mut err = io::stderr().lock();
+                    err.write_all(b"\x07")?;
+                    err.flush()?;
                 }
             }
-            Some(key_event) = event_rx.recv() => {
-                if key_event.code == KeyCode::Char('q') || (key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(crossterm::event::KeyModifiers::CONTROL)) {
+            _ = wait_until(deadline) => exit = true,
+            Some((region, probe)) = rx.recv() => {
+                recorder.record(stats, region, probe, config).await?;
+                app.received += 1;
+            }
+            key_event = event_rx.recv() => {
+                let Some(key_event) = key_event else {
+                    // The event thread hit an error and has already logged it.
+                    break;
+                };
+                // The overlay takes the next key, whichever it is, to close.
+                if std::mem::take(&mut app.show_help) {
+                    continue;
+                }
+                if ui::quits(&key_event) {
                     exit = true;
+                    continue;
+                }
+                match key_event.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        app.move_selection(&stats.lock().await, config, -1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        app.move_selection(&stats.lock().await, config, 1);
+                    }
+                    KeyCode::PageUp => {
+                        let page = app.page_rows as isize;
+                        app.move_selection(&stats.lock().await, config, -page);
+                    }
+                    KeyCode::PageDown => {
+                        let page = app.page_rows as isize;
+                        app.move_selection(&stats.lock().await, config, page);
+                    }
+                    KeyCode::Home => app.move_selection(&stats.lock().await, config, isize::MIN),
+                    KeyCode::End => app.move_selection(&stats.lock().await, config, isize::MAX),
+                    KeyCode::Char('p') => app.toggle_pin(),
+                    KeyCode::Char('d') => app.show_histogram = !app.show_histogram,
+                    KeyCode::Char('v') => app.toggle_compare(),
+                    KeyCode::Char('c') if key_event.modifiers.is_empty() => {
+                        let mut stats = stats.lock().await;
+                        if let Some(stat) = app
+                            .selected
+                            .and_then(|region| stats.iter_mut().find(|stat| stat.region == region))
+                        {
+                            stat.reset();
+                            app.flash = Some((format!("cleared {}", stat.region), Instant::now()));
+                        }
+                    }
+                    KeyCode::Char('C') => {
+                        stats.lock().await.iter_mut().for_each(PingStats::reset);
+                        app.flash = Some(("cleared every region".to_string(), Instant::now()));
+                    }
+                    KeyCode::Char('b') => {
+                        app.sonar = !app.sonar;
+                        let state = if app.sonar { "on" } else { "off" };
+                        app.flash = Some((format!("sonar {}", state), Instant::now()));
+                    }
+                    KeyCode::Char('f') => app.toggle_freeze(&stats.lock().await, config),
+                    KeyCode::Char('o') => app.refresh_order(&stats.lock().await, config),
+                    KeyCode::Char('s') => {
+                        let size = terminal.size()?;
+                        let saved = screenshot::save(&stats.lock().await, app, config, size);
+                        let message = match saved {
+                            Ok(path) => format!("saved {}", path.display()),
+                            Err(err) => format!("screenshot failed: {}", err),
+                        };
+                        app.flash = Some((message, Instant::now()));
+                    }
+                    KeyCode::Char('r') => {
+                        if let Some(notify) = app.selected.and_then(|region| probe_now.get(region)) {
+                            notify.notify_one();
+                        }
+                    }
+                    KeyCode::Char('S') if config.replay.is_none() => {
+                        let message = if app.storm.start(Instant::now()) {
+                            probe_now.values().for_each(|notify| notify.notify_one());
+                            "storm started"
+                        } else {
+                            "a storm is already under way"
+                        };
+                        app.flash = Some((message.to_string(), Instant::now()));
+                    }
+                    KeyCode::Char('?') => app.show_help = true,
+                    KeyCode::Esc => app.selected = None,
+                    _ => {}
                 }
             }
         }
     }
 
-    for handle in handles {
+    drop(event_rx);
+    let _ = events.await;
+    Ok(())
+}
+
+/// Collects samples without a terminal until Ctrl+C or SIGTERM, the
+/// `--run-for` deadline, the `--ndjson` reader going away, or a `--replay`
+/// running out of samples.
+async fn run_headless(
+    stats: &Mutex<Vec<PingStats<'static>>>,
+    rx: &mut mpsc::Receiver<(&'static str, Probe)>,
+    recorder: &mut Recorder,
+    probe_now: &HashMap<&'static str, Arc<Notify>>,
+    storm: &Storm,
+    deadline: Option<Instant>,
+    config: &Config,
+) -> io::Result<()> {
+    let mut shutdown = Shutdown::listen()?;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                handle_storm(storm, stats, probe_now, config).await;
+            }
+            signal = shutdown.recv() => {
+                log::info!("received {}", signal);
+                return Ok(());
+            }
+            _ = wait_until(deadline) => return Ok(()),
+            message = rx.recv() => {
+                let Some((region, probe)) = message else {
+                    return Ok(());
+                };
+                match recorder.record(stats, region, probe, config).await {
+                    Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                    other => other?,
+                }
+            }
+        }
+    }
+}
+
+/// Writes what a finished run leaves behind: the `--resume` checkpoint, the
+/// `--records` file and the `--markdown` and `--json` reports.
+fn save_results(stats: &[PingStats], config: &Config, elapsed: Duration) -> io::Result<()> {
+    if let Some(path) = &config.resume {
+        checkpoint::save(path, stats)?;
+    }
+    if let Some(records) = &config.records {
+        records.save(stats)?;
+    }
+    if let Some(path) = &config.markdown {
+        export::write_markdown(path, stats, config, elapsed)?;
+    }
+    if let Some(path) = &config.json {
+        export::write_json(path, stats, config, elapsed)?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let mut config = match Config::from_args() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, config::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let log_buffer = logging::init(!config.no_tui);
+    if config.help {
+        print!("{}", config::USAGE);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(service) = &config.discover {
+        let discovered = discover::regions(service, config.probe_path.as_deref())
+            .await
+            .and_then(|mut regions| {
+                config.region_filter.apply(&mut regions)?;
+                Ok(regions)
+            });
+        match discovered {
+            Ok(regions) => {
+                log::info!("discovered {} regions offering {}", regions.len(), service);
+                config.regions = regions;
+            }
+            Err(err) => {
+                eprintln!("error: --discover: {}", err);
+                std::process::exit(2);
+            }
+        }
+    }
+    if config.print_config {
+        print!("{}", config.effective_toml());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let replay = match &config.replay {
+        Some(path) => match replay::load(path) {
+            Ok(samples) => {
+                log::info!(
+                    "replaying {} samples from {}",
+                    samples.len(),
+                    path.display()
+                );
+                Some(samples)
+            }
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    };
+    if let Some(samples) = &replay {
+        config.regions = replay::regions(samples);
+    }
+    let config = Arc::new(config);
+
+    let mut client_builder = Client::builder();
+    if config.insecure {
+        client_builder = client_builder.danger_accept_invalid_certs(true);
+    }
+    let mut background = Vec::new();
+    let resolver = (!config.dns_ttl.is_zero()).then(CachingResolver::default);
+    if let Some(resolver) = &resolver {
+        background.push(resolver.spawn_refresh(regions::hosts(&config.regions), config.dns_ttl));
+        client_builder = client_builder.dns_resolver(Arc::new(resolver.clone()));
+    }
+    let client = client_builder.build()?;
+
+    let prober: Arc<dyn Prober> = match config.mode {
+        ProbeMode::Http => Arc::new(HttpProber::new(
+            client,
+            config.method,
+            config.timing,
+            config.timeout,
+        )),
+        ProbeMode::Tcp => Arc::new(TcpProber::new(resolver, config.timeout)),
+        ProbeMode::Icmp => match IcmpProber::new(resolver, config.timeout) {
+            Ok(prober) => Arc::new(prober),
+            Err(err) => {
+                eprintln!(
+                    "error: ICMP mode needs unprivileged ping sockets \
+                     (see net.ipv4.ping_group_range): {}",
+                    err
+                );
+                std::process::exit(1);
+            }
+        },
+        ProbeMode::Quic => match QuicProber::new(resolver, config.timeout) {
+            Ok(prober) => Arc::new(prober),
+            Err(err) => {
+                eprintln!("error: failed to set up QUIC: {}", err);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    if config.preflight {
+        let reachable = preflight::run(prober.as_ref(), &config).await;
+        if reachable < config.preflight_min {
+            std::process::exit(1);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let stats: Arc<Mutex<Vec<PingStats>>> = Arc::new(Mutex::new(
+        config
+            .regions
+            .iter()
+            .map(|region| PingStats::new(region.name, config.stats))
+            .collect(),
+    ));
+
+    if let Some(path) = &config.resume {
+        match checkpoint::load(path, &mut stats.lock().await) {
+            Ok(Some(mismatch)) => {
+                log::info!("resumed from {}", path.display());
+                if !mismatch.unknown.is_empty() {
+                    eprintln!(
+                        "warning: {} has regions that aren't pinged now, dropping them: {}",
+                        path.display(),
+                        mismatch.unknown.join(", ")
+                    );
+                }
+                if !mismatch.missing.is_empty() {
+                    eprintln!(
+                        "warning: {} has no stats for {}, starting them empty",
+                        path.display(),
+                        mismatch.missing.join(", ")
+                    );
+                }
+            }
+            Ok(None) => log::info!("no checkpoint at {} yet", path.display()),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(2);
+            }
+        }
+        background.push(checkpoint::spawn(
+            path.clone(),
+            Arc::clone(&stats),
+            config.checkpoint_interval,
+        ));
+    }
+
+    if let Some(endpoint) = &config.otlp {
+        background.push(otlp::spawn_exporter(
+            Client::new(),
+            endpoint,
+            config.otlp_interval,
+            Arc::clone(&stats),
+        ));
+    }
+
+    let mut app = App::new();
+    if let Some(dir) = &config.snapshot_dir {
+        if let Err(err) = fs::create_dir_all(dir) {
+            eprintln!("error: failed to create {}: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+        background.push(snapshots::spawn(
+            dir.clone(),
+            Arc::clone(&stats),
+            Arc::clone(&config),
+            app.started,
+        ));
+    }
+    if let Some(address) = config.serve {
+        let listener = match TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("error: failed to listen on {}: {}", address, err);
+                std::process::exit(1);
+            }
+        };
+        background.push(serve::spawn(
+            listener,
+            Arc::clone(&stats),
+            Arc::clone(&config),
+            app.started,
+        ));
+    }
+
+    let capacity = (config.regions.len() * CHANNEL_PER_REGION).max(MIN_CHANNEL_CAPACITY);
+    let (tx, mut rx) = mpsc::channel(capacity);
+
+    let probe_now: HashMap<&'static str, Arc<Notify>> = config
+        .regions
+        .iter()
+        .map(|region| (region.name, Arc::new(Notify::new())))
+        .collect();
+    if let Some(duration) = config.storm {
+        app.storm = Arc::new(Storm::new(duration, Some(app.started + duration)));
+    }
+    let handles = match replay {
+        Some(samples) => vec![replay::spawn(samples, config.replay_speed, tx)],
+        None => {
+            start_fetching_latencies(
+                prober,
+                Arc::clone(&config),
+                &probe_now,
+                &app.storm,
+                tx,
+                &app.dropped,
+            )
+            .await
+        }
+    };
+
+    app.sonar = config.sonar;
+    app.phase = warmup::Phase::new(config.warmup, Instant::now());
+    let capture = match &config.record {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                eprintln!("error: failed to create {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let mut recorder = Recorder::new(capture);
+    let deadline = config.run_for.map(|run_for| app.started + run_for);
+    if config.insecure && config.no_tui {
+        eprintln!("warning: TLS certificate validation is disabled (--insecure)");
+    }
+    let result = if config.no_tui {
+        run_headless(
+            &stats,
+            &mut rx,
+            &mut recorder,
+            &probe_now,
+            &app.storm,
+            deadline,
+            &config,
+        )
+        .await
+    } else {
+        run_tui(
+            &mut app,
+            &stats,
+            &mut rx,
+            &mut recorder,
+            &probe_now,
+            deadline,
+            &config,
+        )
+        .await
+    };
+    log::info!(
+        "shutting down after {}",
+        ui::format_elapsed(app.started.elapsed())
+    );
+    let dropped = app.dropped.load(atomic::Ordering::Relaxed);
+    if dropped > 0 {
+        log::warn!("dropped {} samples because they arrived too fast", dropped);
+    }
+    if let Some(buffer) = &log_buffer {
+        buffer.dump()?;
+    }
+    // The capture is finalized even when the run ended in an error.
+    recorder.finish()?;
+    result?;
+
+    for handle in handles.into_iter().chain(background) {
         handle.abort();
     }
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        crossterm::terminal::LeaveAlternateScreen
-    )?;
-    terminal.show_cursor()?;
+    let stats = stats.lock().await;
+    save_results(&stats, &config, app.started.elapsed())?;
 
-    Ok(())
+    // Scripted runs report through the exit status whether every region
+    // answered; an interactive session that's quit by hand always succeeds.
+    if config.run_for.is_none() && config.markdown.is_none() && config.json.is_none() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    let answered = stats.iter().filter(|stat| stat.samples > 0).count();
+    Ok(if answered == stats.len() {
+        ExitCode::SUCCESS
+    } else if answered > 0 {
+        ExitCode::from(EXIT_SOME_FAILED)
+    } else {
+        ExitCode::from(EXIT_ALL_FAILED)
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[tokio::test]
+    async fn a_signal_ends_a_headless_run_with_everything_saved() {
+        let _signals = shutdown::SIGNALS.lock().await;
+        let dir = std::env::temp_dir().join(format!("pong-headless-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            resume: Some(dir.join("checkpoint.json")),
+            json: Some(dir.join("report.json")),
+            ..Config::default()
+        };
+        let stats = Mutex::new(vec![PingStats::new("test", config.stats)]);
+        let (tx, mut rx) = mpsc::channel(4);
+        let probe = Probe {
+            result: Ok(Duration::from_millis(10)),
+            retries: 0,
+            bytes: None,
+        };
+        tx.send(("test", probe)).await.unwrap();
+        let capture = File::create(dir.join("capture.ndjson")).unwrap();
+        let mut recorder = Recorder::new(Some(capture));
+        let storm = Storm::new(storm::DEFAULT_DURATION, None);
+        let probe_now = HashMap::new();
+
+        // With the sender still open and no deadline, only the signal can end
+        // the run. It's sent once run_headless, polled first, is listening.
+        let (result, ()) = tokio::join!(
+            run_headless(
+                &stats,
+                &mut rx,
+                &mut recorder,
+                &probe_now,
+                &storm,
+                None,
+                &config,
+            ),
+            async {
+                sleep(Duration::from_millis(200)).await;
+                let pid = std::process::id().to_string();
+                let sent = Command::new("kill").args(["-TERM", &pid]).status();
+                assert!(sent.unwrap().success());
+            }
+        );
+        result.unwrap();
+        recorder.finish().unwrap();
+        save_results(&stats.lock().await, &config, Duration::from_secs(1)).unwrap();
+
+        let capture = fs::read_to_string(dir.join("capture.ndjson")).unwrap();
+        assert_eq!(capture.lines().count(), 1, "{}", capture);
+        let report = fs::read_to_string(dir.join("report.json")).unwrap();
+        assert!(report.contains(r#""region":"test""#), "{}", report);
+        assert!(dir.join("checkpoint.json").exists());
+        drop(tx);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::Client;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::json;
+use crate::stats::PingStats;
+
+/// Normalizes `--otlp` to the OTLP/HTTP metrics path, accepting either a
+/// collector base URL (`http://localhost:4318`) or the full metrics URL.
+pub fn metrics_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/metrics") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/metrics", endpoint)
+    }
+}
+
+fn gauge(name: &str, unit: &str, time: &str, points: &[(&str, Option<f64>)]) -> String {
+    let points: Vec<String> = points
+        .iter()
+        .filter(|(_, value)| value.is_some())
+        .map(|(region, value)| {
+            format!(
+                r#"{{"attributes":[{{"key":"region","value":{{"stringValue":{}}}}}],"timeUnixNano":"{}","asDouble":{}}}"#,
+                json::string(region),
+                time,
+                json::number(*value)
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"name":{},"unit":{},"gauge":{{"dataPoints":[{}]}}}}"#,
+        json::string(name),
+        json::string(unit),
+        points.join(",")
+    )
+}
+
+/// Encodes the current stats as an OTLP `ExportMetricsServiceRequest` in the
+/// protocol's JSON mapping, with one data point per region. Written by hand
+/// rather than with `opentelemetry-otlp`, as three gauges don't need the SDK.
+pub fn encode(stats: &[PingStats]) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+
+    let avg: Vec<_> = stats.iter().map(|s| (s.region, s.avg())).collect();
+    let p95: Vec<_> = stats.iter().map(|s| (s.region, s.p95())).collect();
+    let loss: Vec<_> = stats.iter().map(|s| (s.region, s.loss())).collect();
+    let metrics = [
+        gauge("pong.latency.avg", "ms", &time, &avg),
+        gauge("pong.latency.p95", "ms", &time, &p95),
+        gauge("pong.loss", "1", &time, &loss),
+    ];
+
+    format!(
+        r#"{{"resourceMetrics":[{{"resource":{{"attributes":[{{"key":"service.name","value":{{"stringValue":"pong"}}}}]}},"scopeMetrics":[{{"scope":{{"name":"pong","version":{}}},"metrics":[{}]}}]}}]}}"#,
+        json::string(env!("CARGO_PKG_VERSION")),
+        metrics.join(",")
+    )
+}
+
+/// Pushes metrics to the collector every `period` from a task of its own. A
+/// slow or failing collector only delays the next export; it never holds the
+/// stats lock while the request is in flight.
+pub fn spawn_exporter(
+    client: Client,
+    endpoint: &str,
+    period: Duration,
+    stats: Arc<Mutex<Vec<PingStats<'static>>>>,
+) -> JoinHandle<()> {
+    let url = metrics_url(endpoint);
+    tokio::spawn(async move {
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            let body = encode(&stats.lock().await);
+            let result = client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .timeout(period)
+                .body(body)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(err) = result {
+                log::warn!("OTLP export to {} failed: {}", url, err);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Value;
+    use crate::stats::StatsOptions;
+
+    fn array(value: Option<&Value>) -> &[Value] {
+        match value {
+            Some(Value::Array(items)) => items,
+            _ => panic!("expected an array, got {:?}", value),
+        }
+    }
+
+    fn text<'a>(value: &'a Value, path: &[&str]) -> Option<&'a str> {
+        path.iter()
+            .try_fold(value, |value, key| value.get(key))?
+            .as_str()
+    }
+
+    #[test]
+    fn encodes_a_gauge_point_per_region() {
+        let mut answering = PingStats::new("eu-west-1", StatsOptions::default());
+        answering.add_latency(Some(Duration::from_millis(20)));
+        let silent = PingStats::new("us-east-1", StatsOptions::default());
+
+        let request = json::parse(&encode(&[answering, silent])).unwrap();
+        let resource = &array(request.get("resourceMetrics"))[0];
+        let scope = &array(resource.get("scopeMetrics"))[0];
+        assert_eq!(text(scope, &["scope", "name"]), Some("pong"));
+        let metrics = array(scope.get("metrics"));
+        let names: Vec<_> = metrics.iter().map(|m| text(m, &["name"])).collect();
+        assert_eq!(
+            names,
+            [
+                Some("pong.latency.avg"),
+                Some("pong.latency.p95"),
+                Some("pong.loss")
+            ]
+        );
+
+        // A region without a value yet has no data point.
+        let points = array(metrics[0].get("gauge").and_then(|g| g.get("dataPoints")));
+        assert_eq!(points.len(), 1);
+        let label = &array(points[0].get("attributes"))[0];
+        assert_eq!(text(label, &["key"]), Some("region"));
+        assert_eq!(text(label, &["value", "stringValue"]), Some("eu-west-1"));
+        assert_eq!(
+            points[0].get("asDouble").and_then(Value::as_f64),
+            Some(20.0)
+        );
+        let time = text(&points[0], &["timeUnixNano"]).unwrap();
+        assert!(time.parse::<u64>().is_ok(), "{}", time);
+    }
+}
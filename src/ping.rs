@@ -0,0 +1,323 @@
+use std::error::Error as _;
+use std::io;
+use std::time::Duration;
+
+use futures::future::join_all;
+use tokio::time::sleep;
+
+use crate::config::ProbeStrategy;
+use crate::prober::{Prober, Target};
+use crate::resolver::DnsError;
+
+/// Why a ping produced no sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PingError {
+    /// The endpoint's hostname could not be resolved.
+    Dns,
+    /// The endpoint doesn't speak the probed protocol, such as a host
+    /// without HTTP/3 in `--mode quic`.
+    Unsupported,
+    /// No answer within the timeout.
+    Timeout,
+    /// The TLS handshake failed, e.g. on an invalid certificate.
+    Tls,
+    /// The connection was refused, reset or couldn't be routed.
+    Connect,
+    /// The server answered with a 5xx status, so the endpoint is up but
+    /// unhealthy and its reply time says little about latency.
+    Http,
+    /// The request failed some other way.
+    Failed,
+}
+
+impl PingError {
+    /// The name used for the error in `--ndjson` lines and `--replay` files.
+    pub fn name(self) -> &'static str {
+        match self {
+            PingError::Dns => "dns",
+            PingError::Unsupported => "unsupported",
+            PingError::Timeout => "timeout",
+            PingError::Tls => "tls",
+            PingError::Connect => "connect",
+            PingError::Http => "http",
+            PingError::Failed => "failed",
+        }
+    }
+
+    /// The error named `name`, the reverse of `name`. Unknown names, such as
+    /// ones from a newer version, count as `Failed`.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "dns" => PingError::Dns,
+            "unsupported" => PingError::Unsupported,
+            "timeout" => PingError::Timeout,
+            "tls" => PingError::Tls,
+            "connect" => PingError::Connect,
+            "http" => PingError::Http,
+            _ => PingError::Failed,
+        }
+    }
+
+    /// A short code for the error column.
+    pub fn code(self) -> &'static str {
+        match self {
+            PingError::Dns => "DNS",
+            PingError::Unsupported => "NO-H3",
+            PingError::Timeout => "TIMEOUT",
+            PingError::Tls => "TLS",
+            PingError::Connect => "CONN",
+            PingError::Http => "HTTP-5xx",
+            PingError::Failed => "FAIL",
+        }
+    }
+}
+
+impl From<&reqwest::Error> for PingError {
+    fn from(err: &reqwest::Error) -> Self {
+        let mut source = err.source();
+        while let Some(err) = source {
+            if err.is::<DnsError>() {
+                return PingError::Dns;
+            }
+            // TLS errors arrive wrapped in `io::Error`s, whose `source`
+            // skips over the error they wrap.
+            let mut inner = err;
+            while let Some(wrapped) = inner
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+            {
+                inner = wrapped;
+            }
+            if inner.is::<rustls::Error>() {
+                return PingError::Tls;
+            }
+            source = err.source();
+        }
+        if err.is_timeout() {
+            PingError::Timeout
+        } else if err.is_connect() {
+            PingError::Connect
+        } else {
+            PingError::Failed
+        }
+    }
+}
+
+impl From<&io::Error> for PingError {
+    fn from(err: &io::Error) -> Self {
+        match err.kind() {
+            // A socket read timeout reports `WouldBlock` on Unix.
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => PingError::Timeout,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable => PingError::Connect,
+            _ => PingError::Failed,
+        }
+    }
+}
+
+pub type PingResult = Result<Duration, PingError>;
+
+/// The outcome of a probe and how many retries it took to get there.
+#[derive(Debug, Clone, Copy)]
+pub struct Probe {
+    pub result: PingResult,
+    pub retries: u32,
+    /// Size of the response body read for a successful probe, when the
+    /// backend reads one.
+    pub bytes: Option<u64>,
+}
+
+/// Retries after a failed probe unless `--retries` says otherwise.
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Pause before each retry.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The longest `ping_with_retries` can take: every attempt timing out after
+/// `timeout`, with the retry delay in between.
+pub fn longest_ping(timeout: Duration, max_retries: u32) -> Duration {
+    timeout
+        .saturating_mul(max_retries.saturating_add(1))
+        .saturating_add(RETRY_DELAY.saturating_mul(max_retries))
+}
+
+/// Probes `target` once, then up to `max_retries` more times while it keeps
+/// failing, so zero reports the first failure straight away.
+pub async fn ping_with_retries(prober: &dyn Prober, target: &Target, max_retries: u32) -> Probe {
+    let mut retries = 0;
+
+    loop {
+        let (result, bytes) = prober.probe_sized(target).await;
+        if result.is_ok() || retries >= max_retries {
+            return Probe {
+                result,
+                retries,
+                bytes,
+            };
+        }
+        retries += 1;
+        log::debug!(
+            "retrying {} ({} of {}) after {:?}",
+            target.url,
+            retries,
+            max_retries,
+            result
+        );
+        sleep(RETRY_DELAY).await;
+    }
+}
+
+/// Pings every endpoint of a region concurrently and combines the results
+/// into the samples to record for this tick. A combined sample counts the
+/// most retries any endpoint needed, and only keeps the response size when
+/// there's a single endpoint.
+pub async fn probe_endpoints(
+    prober: &dyn Prober,
+    targets: &[Target],
+    strategy: ProbeStrategy,
+    max_retries: u32,
+) -> Vec<Probe> {
+    let probes = join_all(
+        targets
+            .iter()
+            .map(|target| ping_with_retries(prober, target, max_retries)),
+    )
+    .await;
+    let results: Vec<PingResult> = probes.iter().map(|probe| probe.result).collect();
+    let retries = probes.iter().map(|probe| probe.retries).max().unwrap_or(0);
+    let successes: Vec<Duration> = results.iter().filter_map(|r| r.ok()).collect();
+
+    let combined = match strategy {
+        ProbeStrategy::All => return probes,
+        ProbeStrategy::Min => successes.iter().min().copied(),
+        ProbeStrategy::Avg => (!successes.is_empty())
+            .then(|| successes.iter().sum::<Duration>() / successes.len() as u32),
+    };
+
+    // When every endpoint failed, report the first failure's cause.
+    let first_error = results.iter().find_map(|r| r.err());
+    let bytes = match probes.as_slice() {
+        [probe] => probe.bytes,
+        _ => None,
+    };
+    vec![Probe {
+        result: combined.ok_or(first_error.unwrap_or(PingError::Failed)),
+        retries,
+        bytes,
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prober::MockProber;
+    use reqwest::Url;
+
+    fn targets(urls: &[&str]) -> Vec<Target> {
+        urls.iter()
+            .map(|url| Target {
+                url: Url::parse(url).unwrap(),
+                timeout: None,
+                auth: None,
+            })
+            .collect()
+    }
+
+    fn ms(ms: u64) -> PingResult {
+        Ok(Duration::from_millis(ms))
+    }
+
+    #[tokio::test]
+    async fn endpoints_combine_per_strategy() {
+        let targets = targets(&["http://a.test", "http://b.test"]);
+        let script: &[(&str, &[PingResult])] = &[
+            ("http://a.test", &[ms(10), ms(10), ms(10)]),
+            ("http://b.test", &[ms(30), ms(30), ms(30)]),
+        ];
+        let prober = MockProber::new(script);
+
+        let min = probe_endpoints(&prober, &targets, ProbeStrategy::Min, DEFAULT_RETRIES).await;
+        assert_eq!(min.len(), 1);
+        assert_eq!(min[0].result, ms(10));
+
+        let avg = probe_endpoints(&prober, &targets, ProbeStrategy::Avg, DEFAULT_RETRIES).await;
+        assert_eq!(avg[0].result, ms(20));
+
+        let all = probe_endpoints(&prober, &targets, ProbeStrategy::All, DEFAULT_RETRIES).await;
+        let results: Vec<PingResult> = all.iter().map(|probe| probe.result).collect();
+        assert_eq!(results, vec![ms(10), ms(30)]);
+    }
+
+    #[tokio::test]
+    async fn failed_endpoints_report_first_error() {
+        let targets = targets(&["http://a.test", "http://b.test"]);
+        let dns: &[PingResult] = &[Err(PingError::Dns); 4];
+        let failed: &[PingResult] = &[Err(PingError::Failed); 4];
+        let prober = MockProber::new(&[("http://a.test", dns), ("http://b.test", failed)]);
+
+        let probes = probe_endpoints(&prober, &targets, ProbeStrategy::Avg, DEFAULT_RETRIES).await;
+        assert_eq!(probes[0].result, Err(PingError::Dns));
+        assert_eq!(probes[0].retries, DEFAULT_RETRIES);
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let targets = targets(&["http://a.test"]);
+        let prober = MockProber::new(&[("http://a.test", &[Err(PingError::Failed), ms(5)])]);
+
+        let probe = ping_with_retries(&prober, &targets[0], DEFAULT_RETRIES).await;
+        assert_eq!(probe.result, ms(5));
+        assert_eq!(probe.retries, 1);
+    }
+
+    #[tokio::test]
+    async fn zero_retries_reports_the_first_failure() {
+        let targets = targets(&["http://a.test"]);
+        let prober = MockProber::new(&[("http://a.test", &[Err(PingError::Timeout), ms(5)])]);
+
+        let probe = ping_with_retries(&prober, &targets[0], 0).await;
+        assert_eq!(probe.result, Err(PingError::Timeout));
+        assert_eq!(probe.retries, 0);
+    }
+
+    #[tokio::test]
+    async fn one_retry_stops_after_the_second_attempt() {
+        let targets = targets(&["http://a.test"]);
+        let script: &[PingResult] = &[
+            Err(PingError::Failed),
+            Err(PingError::Timeout),
+            Err(PingError::Failed),
+            ms(5),
+        ];
+        let prober = MockProber::new(&[("http://a.test", script)]);
+
+        let probe = ping_with_retries(&prober, &targets[0], 1).await;
+        assert_eq!(probe.result, Err(PingError::Timeout));
+        assert_eq!(probe.retries, 1);
+
+        // A success on the retry is kept.
+        let probe = ping_with_retries(&prober, &targets[0], 1).await;
+        assert_eq!(probe.result, ms(5));
+        assert_eq!(probe.retries, 1);
+    }
+
+    #[test]
+    fn error_names_round_trip() {
+        for err in [
+            PingError::Dns,
+            PingError::Unsupported,
+            PingError::Timeout,
+            PingError::Tls,
+            PingError::Connect,
+            PingError::Http,
+            PingError::Failed,
+        ] {
+            assert_eq!(PingError::from_name(err.name()), err);
+        }
+        assert_eq!(PingError::Http.code(), "HTTP-5xx");
+        assert_eq!(PingError::from_name("from-the-future"), PingError::Failed);
+    }
+}
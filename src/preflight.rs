@@ -0,0 +1,58 @@
+use futures::future::join_all;
+
+use crate::config::Config;
+use crate::ping::{PingError, PingResult};
+use crate::prober::Prober;
+use crate::regions::Region;
+use crate::ui::format_latency;
+
+/// Pings every endpoint of a region once, without retries, keeping the
+/// fastest answer or else the first error.
+async fn ping_once(prober: &dyn Prober, region: &Region) -> PingResult {
+    let results = join_all(region.targets.iter().map(|target| prober.probe(target))).await;
+    let fastest = results.iter().filter_map(|result| result.ok()).min();
+    match fastest {
+        Some(latency) => Ok(latency),
+        None => Err(results
+            .iter()
+            .find_map(|result| result.err())
+            .unwrap_or(PingError::Failed)),
+    }
+}
+
+/// Pings every region once and prints a table of the results followed by a
+/// reachable count, for telling a local network problem from a remote one.
+/// Returns how many regions answered.
+pub async fn run(prober: &dyn Prober, config: &Config) -> usize {
+    let results = join_all(
+        config
+            .regions
+            .iter()
+            .map(|region| ping_once(prober, region)),
+    )
+    .await;
+
+    let width = config
+        .regions
+        .iter()
+        .map(|region| region.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (region, result) in config.regions.iter().zip(&results) {
+        let text = match result {
+            Ok(latency) => format_latency(Some(latency.as_secs_f64() * 1000.0), config.precision),
+            Err(PingError::Dns) => "DNS error".to_string(),
+            Err(PingError::Unsupported) => "no HTTP/3".to_string(),
+            Err(PingError::Timeout) => "timed out".to_string(),
+            Err(PingError::Tls) => "TLS error".to_string(),
+            Err(PingError::Connect) => "connection failed".to_string(),
+            Err(PingError::Http) => "server error".to_string(),
+            Err(PingError::Failed) => "failed".to_string(),
+        };
+        println!("{:<width$}  {}", region.name, text, width = width);
+    }
+
+    let reachable = results.iter().filter(|result| result.is_ok()).count();
+    println!("\n{}/{} regions reachable", reachable, results.len());
+    reachable
+}
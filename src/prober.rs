@@ -0,0 +1,509 @@
+use std::fmt;
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, ConnectionError, Endpoint};
+use reqwest::{Client, Method, Url};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::config::{HttpMethod, HttpTiming};
+use crate::ping::{PingError, PingResult};
+use crate::resolver::{self, CachingResolver};
+
+/// How long a single probe may take before it counts as failed, unless
+/// changed with `--timeout`.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An endpoint to probe.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub url: Url,
+    /// Replaces the prober's timeout for this endpoint, from the regions
+    /// file's `timeout` option.
+    pub timeout: Option<Duration>,
+    /// HTTP basic auth sent with every request, taken out of the URL so it
+    /// never shows up where the URL does.
+    pub auth: Option<Credentials>,
+}
+
+/// A username and optional password for HTTP basic auth.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Target {
+    /// How long probing this target may take, given the prober's default.
+    fn timeout_or(&self, default: Duration) -> Duration {
+        self.timeout.unwrap_or(default)
+    }
+}
+
+/// A way of measuring the round trip to a target. The retry and interval loop
+/// only sees this trait, so backends can be swapped with `--mode`.
+pub trait Prober: Send + Sync {
+    fn probe<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, PingResult>;
+
+    /// Like `probe`, also returning how many response body bytes were read,
+    /// for backends that read one.
+    fn probe_sized<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, (PingResult, Option<u64>)> {
+        Box::pin(async move { (self.probe(target).await, None) })
+    }
+}
+
+/// Times an HTTP request to the target URL, `HEAD` unless `--method` says
+/// otherwise.
+pub struct HttpProber {
+    client: Client,
+    method: Method,
+    timing: HttpTiming,
+    timeout: Duration,
+}
+
+impl HttpProber {
+    pub fn new(client: Client, method: HttpMethod, timing: HttpTiming, timeout: Duration) -> Self {
+        let method = match method {
+            HttpMethod::Head => Method::HEAD,
+            HttpMethod::Get => Method::GET,
+            HttpMethod::Options => Method::OPTIONS,
+        };
+        HttpProber {
+            client,
+            method,
+            timing,
+            timeout,
+        }
+    }
+}
+
+impl Prober for HttpProber {
+    fn probe<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, PingResult> {
+        Box::pin(async move { self.probe_sized(target).await.0 })
+    }
+
+    fn probe_sized<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, (PingResult, Option<u64>)> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let mut request = self
+                .client
+                .request(self.method.clone(), target.url.clone())
+                .timeout(target.timeout_or(self.timeout));
+            if let Some(auth) = &target.auth {
+                request = request.basic_auth(&auth.username, auth.password.as_ref());
+            }
+            let result = request.send().await;
+            let mut response = match result {
+                Ok(response) => response,
+                Err(err) => {
+                    log::debug!("{} {} failed: {}", self.method, target.url, err);
+                    return (Err(PingError::from(&err)), None);
+                }
+            };
+            if response.status().is_server_error() {
+                log::debug!(
+                    "{} {} answered {}",
+                    self.method,
+                    target.url,
+                    response.status()
+                );
+                return (Err(PingError::Http), None);
+            }
+            if self.timing == HttpTiming::Ttfb {
+                return (Ok(start.elapsed()), None);
+            }
+            // Drain the body chunk by chunk so nothing is buffered needlessly;
+            // the request timeout still applies while reading.
+            let mut bytes = 0;
+            loop {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => bytes += chunk.len() as u64,
+                    Ok(None) => return (Ok(start.elapsed()), Some(bytes)),
+                    Err(err) => {
+                        log::debug!("reading the body of {} failed: {}", target.url, err);
+                        return (Err(PingError::from(&err)), None);
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Resolves the target's host, through the cache when there is one. Lookups
+/// happen before the clock starts so they don't count toward the latency.
+async fn resolve(
+    resolver: Option<&CachingResolver>,
+    target: &Target,
+) -> Result<SocketAddr, PingError> {
+    let host = target.url.host_str().ok_or(PingError::Failed)?;
+    let port = target.url.port_or_known_default().unwrap_or(0);
+    let addrs = match resolver {
+        Some(resolver) => resolver.lookup(host).await,
+        None => resolver::lookup(host).await,
+    };
+    let addr = addrs
+        .map_err(|err| {
+            log::debug!("resolving {} failed: {}", host, err);
+            PingError::Dns
+        })?
+        .into_iter()
+        .next();
+    let mut addr = addr.ok_or(PingError::Dns)?;
+    addr.set_port(port);
+    Ok(addr)
+}
+
+/// Times a TCP handshake with the target's host and port.
+pub struct TcpProber {
+    resolver: Option<CachingResolver>,
+    timeout: Duration,
+}
+
+impl TcpProber {
+    pub fn new(resolver: Option<CachingResolver>, timeout: Duration) -> Self {
+        TcpProber { resolver, timeout }
+    }
+}
+
+impl Prober for TcpProber {
+    fn probe<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, PingResult> {
+        Box::pin(async move {
+            let addr = resolve(self.resolver.as_ref(), target).await?;
+            let start = Instant::now();
+            let limit = target.timeout_or(self.timeout);
+            match timeout(limit, TcpStream::connect(addr)).await {
+                Ok(Ok(_)) => Ok(start.elapsed()),
+                Ok(Err(err)) => {
+                    log::debug!("TCP connect to {} failed: {}", addr, err);
+                    Err(PingError::from(&err))
+                }
+                Err(_) => {
+                    log::debug!("TCP connect to {} timed out", addr);
+                    Err(PingError::Timeout)
+                }
+            }
+        })
+    }
+}
+
+/// Times an ICMP echo to the target's host. This uses unprivileged ping
+/// sockets, which on Linux must be allowed by `net.ipv4.ping_group_range`.
+pub struct IcmpProber {
+    resolver: Option<CachingResolver>,
+    timeout: Duration,
+}
+
+impl IcmpProber {
+    /// Fails up front if ping sockets can't be opened, rather than reporting
+    /// every region as unreachable.
+    pub fn new(resolver: Option<CachingResolver>, timeout: Duration) -> io::Result<Self> {
+        Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))?;
+        Ok(IcmpProber { resolver, timeout })
+    }
+}
+
+/// Sends one echo request and blocks until the matching reply or timeout.
+/// The kernel fills in the identifier and checksum for ping sockets.
+fn icmp_echo(addr: SocketAddr, limit: Duration) -> io::Result<Duration> {
+    let (domain, protocol, request, reply) = match addr {
+        SocketAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4, 8, 0),
+        SocketAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6, 128, 129),
+    };
+    let socket = Socket::new(domain, Type::DGRAM, Some(protocol))?;
+    socket.set_read_timeout(Some(limit))?;
+
+    let mut packet = [0u8; 16];
+    packet[0] = request;
+    packet[7] = 1; // sequence number
+
+    let start = Instant::now();
+    socket.send_to(&packet, &addr.into())?;
+    let mut buf = [MaybeUninit::<u8>::uninit(); 64];
+    loop {
+        let (len, _) = socket.recv_from(&mut buf)?;
+        // SAFETY: `recv_from` initialized the first `len` bytes.
+        if len > 0 && unsafe { buf[0].assume_init() } == reply {
+            return Ok(start.elapsed());
+        }
+        if start.elapsed() > limit {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+    }
+}
+
+impl Prober for IcmpProber {
+    fn probe<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, PingResult> {
+        Box::pin(async move {
+            let addr = resolve(self.resolver.as_ref(), target).await?;
+            let limit = target.timeout_or(self.timeout);
+            match tokio::task::spawn_blocking(move || icmp_echo(addr, limit)).await {
+                Ok(Ok(elapsed)) => Ok(elapsed),
+                Ok(Err(err)) => {
+                    log::debug!("ICMP echo to {} failed: {}", addr, err);
+                    Err(PingError::from(&err))
+                }
+                Err(err) => {
+                    log::warn!("ICMP echo task failed: {}", err);
+                    Err(PingError::Failed)
+                }
+            }
+        })
+    }
+}
+
+/// QUIC's error code for the TLS `no_application_protocol` alert, which
+/// servers send when they don't offer HTTP/3.
+const NO_APPLICATION_PROTOCOL: u64 = 0x100 | 120;
+
+/// Times a QUIC handshake with the target, offering HTTP/3. This covers the
+/// connection setup an HTTP/3 client pays before its first request.
+pub struct QuicProber {
+    endpoint_v4: Endpoint,
+    endpoint_v6: Option<Endpoint>,
+    resolver: Option<CachingResolver>,
+    timeout: Duration,
+}
+
+impl QuicProber {
+    pub fn new(
+        resolver: Option<CachingResolver>,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let client_config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls)?));
+
+        let endpoint = |addr: &str| -> io::Result<Endpoint> {
+            let mut endpoint = Endpoint::client(addr.parse().unwrap())?;
+            endpoint.set_default_client_config(client_config.clone());
+            Ok(endpoint)
+        };
+        Ok(QuicProber {
+            endpoint_v4: endpoint("0.0.0.0:0")?,
+            // Hosts without IPv6 simply never resolve to a v6 address.
+            endpoint_v6: endpoint("[::]:0").ok(),
+            resolver,
+            timeout,
+        })
+    }
+}
+
+impl Prober for QuicProber {
+    fn probe<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, PingResult> {
+        Box::pin(async move {
+            let addr = resolve(self.resolver.as_ref(), target).await?;
+            let host = target.url.host_str().ok_or(PingError::Failed)?;
+            let endpoint = match addr {
+                SocketAddr::V4(_) => &self.endpoint_v4,
+                SocketAddr::V6(_) => self.endpoint_v6.as_ref().ok_or(PingError::Failed)?,
+            };
+
+            let start = Instant::now();
+            let connecting = endpoint
+                .connect(addr, host)
+                .map_err(|_| PingError::Failed)?;
+            match timeout(target.timeout_or(self.timeout), connecting).await {
+                Ok(Ok(connection)) => {
+                    let elapsed = start.elapsed();
+                    connection.close(0u32.into(), b"");
+                    Ok(elapsed)
+                }
+                Ok(Err(ConnectionError::ConnectionClosed(close)))
+                    if u64::from(close.error_code) == NO_APPLICATION_PROTOCOL =>
+                {
+                    Err(PingError::Unsupported)
+                }
+                Ok(Err(err)) => {
+                    log::debug!("QUIC handshake with {} failed: {}", addr, err);
+                    // Error codes 0x100 to 0x1ff carry TLS alerts.
+                    let tls = |code: u64| (0x100..0x200).contains(&code);
+                    Err(match err {
+                        ConnectionError::TimedOut => PingError::Timeout,
+                        ConnectionError::TransportError(err) if tls(u64::from(err.code)) => {
+                            PingError::Tls
+                        }
+                        ConnectionError::ConnectionClosed(close)
+                            if tls(u64::from(close.error_code)) =>
+                        {
+                            PingError::Tls
+                        }
+                        _ => PingError::Connect,
+                    })
+                }
+                Err(_) => {
+                    log::debug!("QUIC handshake with {} timed out", addr);
+                    Err(PingError::Timeout)
+                }
+            }
+        })
+    }
+}
+
+/// Replays scripted results per URL, for deterministic tests. A target with
+/// nothing left to replay fails.
+#[cfg(test)]
+pub struct MockProber {
+    script: std::sync::Mutex<std::collections::HashMap<String, Vec<PingResult>>>,
+}
+
+#[cfg(test)]
+impl MockProber {
+    pub fn new(script: &[(&str, &[PingResult])]) -> Self {
+        let script = script
+            .iter()
+            .map(|(url, results)| {
+                let mut results = results.to_vec();
+                results.reverse();
+                (Url::parse(url).unwrap().to_string(), results)
+            })
+            .collect();
+        MockProber {
+            script: std::sync::Mutex::new(script),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Prober for MockProber {
+    fn probe<'a>(&'a self, target: &'a Target) -> BoxFuture<'a, PingResult> {
+        let mut script = self.script.lock().unwrap();
+        let result = script
+            .get_mut(target.url.as_str())
+            .and_then(Vec::pop)
+            .unwrap_or(Err(PingError::Failed));
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeStrategy;
+    use crate::ping::{probe_endpoints, DEFAULT_RETRIES};
+    use crate::regions::parse_regions_file;
+    use crate::stats::{PingStats, StatsOptions};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A local server that answers each request after the delay in
+    /// milliseconds given by its path, e.g. `/40`. It stands in for a region
+    /// with a known latency, so the whole path from a regions file through
+    /// the HTTP prober to the stats can be checked against it. A path like
+    /// `/status/503` answers at once with that status instead.
+    async fn delay_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buf[..read]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let status = path.strip_prefix("/status/").unwrap_or("200");
+                    let delay = path.trim_start_matches('/').parse().unwrap_or(0);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    let response = format!(
+                        "HTTP/1.1 {} Status\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                        status
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn http_prober_measures_injected_latency() {
+        let addr = delay_server().await;
+        let text = format!("fast,http://{0}/20\nslow,http://{0}/80\n", addr);
+        let regions = parse_regions_file(&text, None).unwrap();
+        // A proxy from the environment would add its own latency.
+        let client = Client::builder().no_proxy().build().unwrap();
+        let prober = HttpProber::new(client, HttpMethod::Get, HttpTiming::Complete, PING_TIMEOUT);
+
+        for (region, injected) in regions.iter().zip([20.0, 80.0]) {
+            let mut stats = PingStats::new(region.name, StatsOptions::default());
+            for _ in 0..5 {
+                for probe in probe_endpoints(
+                    &prober,
+                    &region.targets,
+                    ProbeStrategy::All,
+                    DEFAULT_RETRIES,
+                )
+                .await
+                {
+                    stats.add_probe(probe);
+                }
+            }
+            assert_eq!(stats.samples, 5);
+            assert_eq!(stats.loss(), Some(0.0));
+            assert_eq!(stats.last_bytes, Some(2));
+            // Each sample is the injected delay plus a little for the
+            // connection and the request itself, never less.
+            let (min, avg) = (stats.min().unwrap(), stats.avg().unwrap());
+            assert!(min >= injected, "{}: min {} ms", region.name, min);
+            assert!(avg < injected + 50.0, "{}: avg {} ms", region.name, avg);
+        }
+    }
+
+    #[tokio::test]
+    async fn failures_are_classified() {
+        let addr = delay_server().await;
+        // Nothing listens on a port whose listener was just dropped.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let target = |url: String| Target {
+            url: Url::parse(&url).unwrap(),
+            timeout: Some(Duration::from_millis(50)),
+            auth: None,
+        };
+        let client = Client::builder().no_proxy().build().unwrap();
+        let http = HttpProber::new(client, HttpMethod::Head, HttpTiming::Complete, PING_TIMEOUT);
+        let tcp = TcpProber::new(None, PING_TIMEOUT);
+
+        let slow = target(format!("http://{}/500", addr));
+        assert_eq!(http.probe(&slow).await, Err(PingError::Timeout));
+        let refused = target(format!("http://{}/", closed));
+        assert_eq!(http.probe(&refused).await, Err(PingError::Connect));
+        assert_eq!(tcp.probe(&refused).await, Err(PingError::Connect));
+        let unavailable = target(format!("http://{}/status/503", addr));
+        assert_eq!(http.probe(&unavailable).await, Err(PingError::Http));
+        // Other statuses still measure a round trip.
+        let missing = target(format!("http://{}/status/404", addr));
+        assert!(http.probe(&missing).await.is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::stats::PingStats;
+
+/// Weights for ranking regions by recent median latency and loss. The score
+/// is `latency * median_ms + loss * loss_pct`, so with the defaults each
+/// percent of loss costs as much as 10 ms of extra latency.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreWeights {
+    pub latency: f64,
+    pub loss: f64,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            latency: 1.0,
+            loss: 10.0,
+        }
+    }
+}
+
+impl FromStr for ScoreWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid weights '{}', expected LATENCY,LOSS", s);
+        let (latency, loss) = s.split_once(',').ok_or_else(invalid)?;
+        let latency: f64 = latency.trim().parse().map_err(|_| invalid())?;
+        let loss: f64 = loss.trim().parse().map_err(|_| invalid())?;
+        if latency < 0.0 || loss < 0.0 {
+            return Err(invalid());
+        }
+        Ok(ScoreWeights { latency, loss })
+    }
+}
+
+/// A region's score and the inputs it was computed from. Lower is better.
+#[derive(Debug, Clone, Copy)]
+pub struct Score {
+    pub median: f64,
+    pub loss_pct: f64,
+    pub value: f64,
+}
+
+/// Scores a region over its recent history, or `None` if it has no successful
+/// sample in that window.
+pub fn score(stat: &PingStats, weights: ScoreWeights) -> Option<Score> {
+    let median = stat.recent_median()?;
+    let loss_pct = stat.recent_loss().unwrap_or(0.0) * 100.0;
+    Some(Score {
+        median,
+        loss_pct,
+        value: weights.latency * median + weights.loss * loss_pct,
+    })
+}
+
+/// The region with the lowest score, if any region has one.
+pub fn recommend<'a, 'r>(
+    stats: &'a [PingStats<'r>],
+    weights: ScoreWeights,
+) -> Option<(&'a PingStats<'r>, Score)> {
+    stats
+        .iter()
+        .filter_map(|stat| score(stat, weights).map(|score| (stat, score)))
+        .min_by(|(_, a), (_, b)| a.value.total_cmp(&b.value))
+}
+
+/// How much lower a challenger's score must be than the leader's, as a
+/// fraction of the leader's, to count as beating it.
+const SWITCH_MARGIN: f64 = 0.05;
+/// How long a challenger must keep beating the leader before taking over.
+const SWITCH_AFTER: Duration = Duration::from_secs(3);
+
+/// The recommended region with hysteresis, so near-tied regions don't trade
+/// places on every redraw. Another region only takes over once it has beaten
+/// the leader by `SWITCH_MARGIN` for `SWITCH_AFTER` without interruption.
+#[derive(Debug, Default)]
+pub struct Leader {
+    current: Option<String>,
+    /// The region currently beating the leader, and since when.
+    challenger: Option<(String, Instant)>,
+}
+
+impl Leader {
+    /// Updates the leader from the latest scores and returns it.
+    pub fn update<'a, 'r>(
+        &mut self,
+        stats: &'a [PingStats<'r>],
+        weights: ScoreWeights,
+    ) -> Option<(&'a PingStats<'r>, Score)> {
+        self.update_at(stats, weights, Instant::now())
+    }
+
+    fn update_at<'a, 'r>(
+        &mut self,
+        stats: &'a [PingStats<'r>],
+        weights: ScoreWeights,
+        now: Instant,
+    ) -> Option<(&'a PingStats<'r>, Score)> {
+        let best = recommend(stats, weights)?;
+        // A leader that lost its score, e.g. to an outage, is replaced at once.
+        let leader = self.current.as_deref().and_then(|region| {
+            let stat = stats.iter().find(|stat| stat.region == region)?;
+            score(stat, weights).map(|score| (stat, score))
+        });
+        let Some(leader) = leader else {
+            self.current = Some(best.0.region.to_string());
+            self.challenger = None;
+            return Some(best);
+        };
+
+        let beaten = best.0.region != leader.0.region
+            && best.1.value < leader.1.value * (1.0 - SWITCH_MARGIN);
+        if !beaten {
+            self.challenger = None;
+            return Some(leader);
+        }
+        match &self.challenger {
+            Some((region, since)) if region == best.0.region => {
+                if now.duration_since(*since) >= SWITCH_AFTER {
+                    self.current = Some(best.0.region.to_string());
+                    self.challenger = None;
+                    return Some(best);
+                }
+            }
+            _ => self.challenger = Some((best.0.region.to_string(), now)),
+        }
+        Some(leader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsOptions;
+
+    fn region(name: &'static str, ms: u64) -> PingStats<'static> {
+        let mut stats = PingStats::new(name, StatsOptions::default());
+        stats.add_latency(Some(Duration::from_millis(ms)));
+        stats
+    }
+
+    #[test]
+    fn leader_changes_only_after_a_sustained_margin() {
+        let weights = ScoreWeights::default();
+        let mut leader = Leader::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let stats = [region("a", 100), region("b", 98)];
+        assert_eq!(
+            leader
+                .update_at(&stats[..1], weights, at(0))
+                .unwrap()
+                .0
+                .region,
+            "a"
+        );
+        // Within the margin, the leader holds however long it lasts.
+        assert_eq!(
+            leader.update_at(&stats, weights, at(10)).unwrap().0.region,
+            "a"
+        );
+
+        let stats = [region("a", 100), region("b", 90)];
+        assert_eq!(
+            leader.update_at(&stats, weights, at(11)).unwrap().0.region,
+            "a"
+        );
+        assert_eq!(
+            leader.update_at(&stats, weights, at(13)).unwrap().0.region,
+            "a"
+        );
+        assert_eq!(
+            leader.update_at(&stats, weights, at(14)).unwrap().0.region,
+            "b"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::json::{self, Value};
+use crate::stats::PingStats;
+
+/// Each region's lowest latency ever seen, kept in a file across runs with
+/// `--records`. Regions that aren't pinged in a run keep their records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Records {
+    path: PathBuf,
+    /// The records as of the start of the run, in milliseconds by region.
+    best: BTreeMap<String, f64>,
+}
+
+impl Records {
+    /// Reads the records at `path`. A missing file is an empty set of
+    /// records, as on the first run.
+    pub fn load(path: &Path) -> Result<Records, String> {
+        let best = match fs::read_to_string(path) {
+            Ok(text) => parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(format!("failed to read {}: {}", path.display(), err)),
+        };
+        Ok(Records {
+            path: path.to_path_buf(),
+            best,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The region's record including this run: the lower of the stored one
+    /// and the session minimum.
+    pub fn record(&self, stat: &PingStats) -> Option<f64> {
+        match (self.best.get(stat.region), stat.session_min()) {
+            (Some(&best), Some(min)) => Some(best.min(min)),
+            (best, min) => best.copied().or(min),
+        }
+    }
+
+    /// Whether this run has beaten the region's stored record, or set its
+    /// first one.
+    pub fn beaten(&self, stat: &PingStats) -> bool {
+        match (self.best.get(stat.region), stat.session_min()) {
+            (Some(&best), Some(min)) => min < best,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+
+    /// The stored records updated with this run's.
+    fn updated(&self, stats: &[PingStats]) -> BTreeMap<String, f64> {
+        let mut best = self.best.clone();
+        for stat in stats {
+            if let Some(record) = self.record(stat) {
+                best.insert(stat.region.to_string(), record);
+            }
+        }
+        best
+    }
+
+    /// Writes the records back to their file if any region beat its own.
+    /// As with checkpoints, a temporary file is renamed over the old one.
+    pub fn save(&self, stats: &[PingStats]) -> io::Result<()> {
+        if !stats.iter().any(|stat| self.beaten(stat)) {
+            return Ok(());
+        }
+        let mut temporary = OsString::from(&self.path);
+        temporary.push(".tmp");
+        fs::write(&temporary, render(&self.updated(stats)))?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+/// The records file: `{"records":{"<region>":<ms>,...}}`, one region per
+/// line so it diffs well.
+fn render(best: &BTreeMap<String, f64>) -> String {
+    let lines: Vec<String> = best
+        .iter()
+        .map(|(region, ms)| format!("  {}: {}", json::string(region), json::number(Some(*ms))))
+        .collect();
+    format!("{{\"records\": {{\n{}\n}}}}\n", lines.join(",\n"))
+}
+
+fn parse(text: &str) -> Result<BTreeMap<String, f64>, String> {
+    let document = json::parse(text)?;
+    let Some(Value::Object(records)) = document.get("records") else {
+        return Err("expected a \"records\" object".to_string());
+    };
+    records
+        .iter()
+        .map(|(region, ms)| match ms.as_f64() {
+            Some(ms) => Ok((region.clone(), ms)),
+            None => Err(format!("{}: expected a latency", region)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsOptions;
+    use std::time::Duration;
+
+    #[test]
+    fn keeps_the_lowest_latency_across_runs() {
+        let records = Records {
+            path: PathBuf::new(),
+            best: parse(r#"{"records":{"faster":5,"slower":50,"idle":7.5}}"#).unwrap(),
+        };
+        let options = StatsOptions::default();
+        let mut stats = vec![
+            PingStats::new("faster", options),
+            PingStats::new("slower", options),
+            PingStats::new("new", options),
+            PingStats::new("down", options),
+        ];
+        stats[0].add_latency(Some(Duration::from_millis(8)));
+        stats[1].add_latency(Some(Duration::from_millis(20)));
+        stats[2].add_latency(Some(Duration::from_millis(30)));
+        stats[3].add_latency(None);
+
+        let records_now: Vec<Option<f64>> = stats.iter().map(|s| records.record(s)).collect();
+        assert_eq!(records_now, [Some(5.0), Some(20.0), Some(30.0), None]);
+        let beaten: Vec<bool> = stats.iter().map(|s| records.beaten(s)).collect();
+        assert_eq!(beaten, [false, true, true, false]);
+
+        let saved = parse(&render(&records.updated(&stats))).unwrap();
+        let expected = [
+            ("faster", 5.0),
+            ("idle", 7.5),
+            ("new", 30.0),
+            ("slower", 20.0),
+        ];
+        assert_eq!(
+            saved,
+            expected
+                .iter()
+                .map(|&(region, ms)| (region.to_string(), ms))
+                .collect()
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+
+use crate::export::ExportUnit;
+use crate::json::{self, Value};
+use crate::ping::{PingError, Probe};
+use crate::regions::Region;
+
+/// A recorded sample, timed relative to the start of the capture.
+pub struct Sample {
+    pub offset: Duration,
+    pub region: &'static str,
+    pub probe: Probe,
+}
+
+/// Parses a capture in the `--ndjson` format. A `null` latency is a failed
+/// ping; the optional `error`, `retries` and `bytes` fields refine it.
+pub fn parse(text: &str) -> Result<Vec<Sample>, String> {
+    let mut samples = Vec::new();
+    let mut names: Vec<&'static str> = Vec::new();
+    let mut start = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+
+        let value = json::parse(line).map_err(error)?;
+        let time = value
+            .get("timestamp")
+            .and_then(Value::as_str)
+            .and_then(json::parse_timestamp)
+            .ok_or_else(|| error("missing or invalid timestamp".to_string()))?;
+        let region = value
+            .get("region")
+            .and_then(Value::as_str)
+            .ok_or_else(|| error("missing region".to_string()))?;
+        let result = match ExportUnit::read(&value, "latency").map_err(error)? {
+            Some(ms) => Ok(Duration::try_from_secs_f64(ms / 1000.0)
+                .map_err(|_| error(format!("invalid latency {} ms", ms)))?),
+            None => Err(value
+                .get("error")
+                .and_then(Value::as_str)
+                .map_or(PingError::Failed, PingError::from_name)),
+        };
+        let retries = value
+            .get("retries")
+            .and_then(Value::as_f64)
+            .map_or(0, |retries| retries as u32);
+        let bytes = value
+            .get("bytes")
+            .and_then(Value::as_f64)
+            .map(|bytes| bytes as u64);
+
+        let start = *start.get_or_insert(time);
+        let region = match names.iter().find(|&&name| name == region) {
+            Some(&name) => name,
+            None => {
+                // Like custom regions, names live for the whole run.
+                let name: &'static str = Box::leak(region.to_string().into_boxed_str());
+                names.push(name);
+                name
+            }
+        };
+        samples.push(Sample {
+            offset: time.duration_since(start).unwrap_or_default(),
+            region,
+            probe: Probe {
+                result,
+                retries,
+                bytes,
+            },
+        });
+    }
+
+    if samples.is_empty() {
+        return Err("no samples to replay".to_string());
+    }
+    Ok(samples)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Sample>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// The regions of a capture in order of first appearance. They have no
+/// targets since nothing is pinged during a replay.
+pub fn regions(samples: &[Sample]) -> Vec<Region> {
+    let mut regions: Vec<Region> = Vec::new();
+    for sample in samples {
+        if !regions.iter().any(|region| region.name == sample.region) {
+            regions.push(Region {
+                name: sample.region,
+                targets: Vec::new(),
+            });
+        }
+    }
+    regions
+}
+
+/// Sends the samples down the same channel the ping tasks use, keeping their
+/// recorded spacing sped up by `speed`.
+pub fn spawn(
+    samples: Vec<Sample>,
+    speed: f64,
+    tx: mpsc::Sender<(&'static str, Probe)>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let start = Instant::now();
+        for sample in samples {
+            sleep_until(start + sample.offset.div_f64(speed)).await;
+            if tx.send((sample.region, sample.probe)).await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{ndjson_line, ExportUnit};
+    use crate::stats::{PingStats, StatsOptions};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn recorded_lines_round_trip() {
+        let probes = [
+            Probe {
+                result: Ok(Duration::from_nanos(23_456_789)),
+                retries: 0,
+                bytes: Some(5120),
+            },
+            Probe {
+                result: Err(PingError::Dns),
+                retries: 3,
+                bytes: None,
+            },
+            Probe {
+                result: Err(PingError::Unsupported),
+                retries: 0,
+                bytes: None,
+            },
+            Probe {
+                result: Ok(Duration::from_nanos(1_000_000_001)),
+                retries: 2,
+                bytes: None,
+            },
+        ];
+        let mut stat = PingStats::new("eu-west-1", StatsOptions::default());
+        let mut text = String::new();
+        for (i, &probe) in probes.iter().enumerate() {
+            stat.add_probe(probe);
+            let time = UNIX_EPOCH + Duration::from_millis(1_760_000_000_000 + 250 * i as u64);
+            // Captures read back whichever unit they were exported in.
+            let unit = [ExportUnit::Ms, ExportUnit::S][i % 2];
+            text.push_str(&ndjson_line(&stat, probe, time, unit));
+            text.push('\n');
+        }
+
+        let samples = parse(&text).unwrap();
+        assert_eq!(samples.len(), probes.len());
+        for (i, (sample, probe)) in samples.iter().zip(&probes).enumerate() {
+            assert_eq!(sample.region, "eu-west-1");
+            assert_eq!(sample.offset, Duration::from_millis(250 * i as u64));
+            assert_eq!(sample.probe.result, probe.result);
+            assert_eq!(sample.probe.retries, probe.retries);
+            assert_eq!(sample.probe.bytes, probe.bytes);
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        let line = |timestamp: &str, latency: &str| {
+            format!(
+                r#"{{"timestamp":"{}","region":"eu-west-1","latency_ms":{}}}"#,
+                timestamp, latency
+            )
+        };
+        let good = line("2025-01-01T00:00:00Z", "5");
+        for (bad, expected) in [
+            (
+                line("2025-01-01T00:00:01Z", "-5"),
+                "line 2: invalid latency -5 ms",
+            ),
+            (
+                line("2025-01-01T00:00:01Z", "1e300"),
+                "line 2: invalid latency",
+            ),
+            (
+                line("2025-01-01T00:00:-1Z", "5"),
+                "line 2: missing or invalid timestamp",
+            ),
+        ] {
+            let err = parse(&format!("{}\n{}\n", good, bad)).err().unwrap();
+            assert!(err.starts_with(expected), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::future::join_all;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tokio::net::lookup_host;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+/// A hostname that could not be resolved. Pings fail with this as the error
+/// source, which lets them be told apart from network timeouts.
+#[derive(Debug)]
+pub struct DnsError {
+    pub host: String,
+    pub source: io::Error,
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to resolve {}: {}", self.host, self.source)
+    }
+}
+
+impl std::error::Error for DnsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+pub async fn lookup(host: &str) -> Result<Vec<SocketAddr>, DnsError> {
+    // Port 0 is replaced by the connector with the URL's port.
+    match lookup_host((host, 0)).await {
+        Ok(addrs) => Ok(addrs.collect()),
+        Err(source) => Err(DnsError {
+            host: host.to_string(),
+            source,
+        }),
+    }
+}
+
+/// Resolves each host once and serves pings from the cached addresses, so
+/// DNS lookups don't add noise to the measured latency. The cache is kept
+/// fresh by `spawn_refresh` rather than on the ping path.
+#[derive(Clone, Default)]
+pub struct CachingResolver {
+    cache: Arc<RwLock<HashMap<String, Vec<SocketAddr>>>>,
+}
+
+impl CachingResolver {
+    /// Resolves `host` and stores the result, keeping any previously cached
+    /// addresses if the lookup fails.
+    pub async fn refresh(&self, host: &str) -> Result<Vec<SocketAddr>, DnsError> {
+        let addrs = lookup(host).await?;
+        self.cache
+            .write()
+            .unwrap()
+            .insert(host.to_string(), addrs.clone());
+        Ok(addrs)
+    }
+
+    /// The cached addresses for `host`, resolving it if it isn't cached yet.
+    pub async fn lookup(&self, host: &str) -> Result<Vec<SocketAddr>, DnsError> {
+        let cached = self.cache.read().unwrap().get(host).cloned();
+        match cached {
+            Some(addrs) => Ok(addrs),
+            None => self.refresh(host).await,
+        }
+    }
+
+    /// Resolves every host concurrently, logging those that fail.
+    pub async fn refresh_all<'a>(&self, hosts: impl IntoIterator<Item = &'a str>) {
+        for result in join_all(hosts.into_iter().map(|host| self.refresh(host))).await {
+            if let Err(err) = result {
+                log::warn!("{}", err);
+            }
+        }
+    }
+
+    /// Resolves `hosts` in the background, then re-resolves every cached host
+    /// each `ttl`. Pings that start before the first lookup completes resolve
+    /// their host on demand.
+    pub fn spawn_refresh(&self, hosts: Vec<String>, ttl: Duration) -> JoinHandle<()> {
+        let resolver = self.clone();
+        tokio::spawn(async move {
+            resolver.refresh_all(hosts.iter().map(String::as_str)).await;
+            loop {
+                sleep(ttl).await;
+                let hosts: Vec<String> = resolver.cache.read().unwrap().keys().cloned().collect();
+                resolver.refresh_all(hosts.iter().map(String::as_str)).await;
+            }
+        })
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
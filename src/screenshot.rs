@@ -0,0 +1,201 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Size;
+use ratatui::style::Modifier;
+use ratatui::Terminal;
+
+use crate::config::Config;
+use crate::json;
+use crate::stats::PingStats;
+use crate::theme;
+use crate::ui;
+use crate::App;
+
+/// File format of the snapshots written with the `s` key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    /// Plain text, without colors.
+    #[default]
+    Text,
+    /// An SVG image with the theme's colors.
+    Svg,
+}
+
+impl FromStr for ScreenshotFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(ScreenshotFormat::Text),
+            "svg" => Ok(ScreenshotFormat::Svg),
+            _ => Err(format!(
+                "invalid screenshot format '{}', expected txt or svg",
+                s
+            )),
+        }
+    }
+}
+
+/// Size of a terminal cell in the SVG, in pixels.
+const CELL_WIDTH: u32 = 9;
+const CELL_HEIGHT: u32 = 18;
+/// Distance from the top of a cell to the text's baseline.
+const BASELINE: u32 = 14;
+/// Colors for cells that use the terminal's defaults.
+const DEFAULT_FG: (u8, u8, u8) = (229, 229, 229);
+const DEFAULT_BG: (u8, u8, u8) = (0, 0, 0);
+
+/// The buffer as lines of text, with trailing spaces trimmed.
+fn to_text(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut out = String::new();
+    for y in area.top()..area.bottom() {
+        let line: String = (area.left()..area.right())
+            .map(|x| buffer[(x, y)].symbol())
+            .collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The buffer as an SVG image. Each run of equally styled cells becomes one
+/// text element stretched to its cells' width, so columns line up whatever
+/// monospace font the viewer picks.
+fn to_svg(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let width = u32::from(area.width) * CELL_WIDTH;
+    let height = u32::from(area.height) * CELL_HEIGHT;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="15">"#,
+        width, height
+    );
+    let _ = writeln!(
+        out,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(DEFAULT_BG)
+    );
+
+    for y in area.top()..area.bottom() {
+        let mut x = area.left();
+        while x < area.right() {
+            let style = buffer[(x, y)].style();
+            let start = x;
+            let mut text = String::new();
+            while x < area.right() && buffer[(x, y)].style() == style {
+                text.push_str(buffer[(x, y)].symbol());
+                x += 1;
+            }
+
+            let mut fg = style.fg.and_then(theme::rgb).unwrap_or(DEFAULT_FG);
+            let mut bg = style.bg.and_then(theme::rgb);
+            if style.add_modifier.contains(Modifier::REVERSED) {
+                (fg, bg) = (bg.unwrap_or(DEFAULT_BG), Some(fg));
+            }
+            let left = u32::from(start - area.left()) * CELL_WIDTH;
+            let top = u32::from(y - area.top()) * CELL_HEIGHT;
+            let run_width = u32::from(x - start) * CELL_WIDTH;
+            if let Some(bg) = bg {
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    left,
+                    top,
+                    run_width,
+                    CELL_HEIGHT,
+                    hex(bg)
+                );
+            }
+            if text.trim().is_empty() {
+                continue;
+            }
+            let weight = if style.add_modifier.contains(Modifier::BOLD) {
+                r#" font-weight="bold""#
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                out,
+                r#"<text x="{}" y="{}" fill="{}"{} textLength="{}" lengthAdjust="spacingAndGlyphs" xml:space="preserve">{}</text>"#,
+                left,
+                top + BASELINE,
+                hex(fg),
+                weight,
+                run_width,
+                escape_xml(&text)
+            );
+        }
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// `time` for use in a file name, e.g. `2024-05-01T12-30-00`. Seconds are
+/// enough to tell files apart, and colons aren't allowed in Windows file
+/// names.
+pub fn file_stamp(time: SystemTime) -> String {
+    json::timestamp(time)[..19].replace(':', "-")
+}
+
+/// Renders the TUI at `size` and writes it to a timestamped file in the
+/// current directory, returning the file's path.
+pub fn save(
+    stats: &[PingStats],
+    app: &mut App,
+    config: &Config,
+    size: Size,
+) -> io::Result<PathBuf> {
+    let mut terminal = Terminal::new(TestBackend::new(size.width, size.height))?;
+    let frame = terminal.draw(|f| ui::draw_table(f, stats, app, config))?;
+    let (contents, extension) = match config.screenshot_format {
+        ScreenshotFormat::Text => (to_text(frame.buffer), "txt"),
+        ScreenshotFormat::Svg => (to_svg(frame.buffer), "svg"),
+    };
+
+    let path = PathBuf::from(format!(
+        "pong-{}.{}",
+        file_stamp(SystemTime::now()),
+        extension
+    ));
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::layout::Rect;
+    use ratatui::style::{Color, Style};
+
+    #[test]
+    fn snapshots_keep_text_and_colors() {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 8, 2));
+        buffer.set_string(0, 0, "a<b", Style::default().fg(Color::Red));
+        buffer.set_string(0, 1, "ok", Style::default());
+
+        assert_eq!(to_text(&buffer), "a<b\nok\n");
+        let svg = to_svg(&buffer);
+        assert!(svg.contains(r##"fill="#cd0000""##));
+        assert!(svg.contains(">a&lt;b</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use crate::config::Config;
+use crate::export;
+use crate::stats::PingStats;
+
+/// Longest request head read before giving up on a client.
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// How long a client has to send its request head. A connection that opens
+/// and then stalls would otherwise keep its task around forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parses `--serve`: a bare port listens on localhost only, while an
+/// `address:port` pair can expose the API to other machines.
+pub fn parse_address(s: &str) -> Result<SocketAddr, String> {
+    match s.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::from(([127, 0, 0, 1], port))),
+        Err(_) => s.parse().map_err(|_| {
+            format!(
+                "invalid --serve address '{}', expected PORT or ADDR:PORT",
+                s
+            )
+        }),
+    }
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    // CORS headers let a dashboard served from anywhere fetch the stats.
+    format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, OPTIONS\r\n\
+         Access-Control-Allow-Headers: *\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Answers one request and closes the connection. One `GET` at a time is all
+/// the API needs, so this is plain HTTP/1.1 over tokio, not a web framework.
+async fn handle(
+    mut stream: TcpStream,
+    stats: &Mutex<Vec<PingStats<'static>>>,
+    config: &Config,
+    started: Instant,
+) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    let read_head = async {
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 || request.len() + read > MAX_REQUEST {
+                return Ok(false);
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        Ok::<_, io::Error>(true)
+    };
+    let complete = match timeout(REQUEST_TIMEOUT, read_head).await {
+        Ok(complete) => complete?,
+        Err(_) => {
+            log::debug!("dropping an API client that sent no request in time");
+            false
+        }
+    };
+    if !complete {
+        return Ok(());
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or(target);
+    let reply = match (method, path) {
+        ("OPTIONS", _) => response("204 No Content", "text/plain", ""),
+        ("GET", "/stats") => {
+            let body = export::json_report(&stats.lock().await, config, started.elapsed());
+            response("200 OK", "application/json", &body)
+        }
+        ("GET", _) => response("404 Not Found", "text/plain", "not found\n"),
+        _ => response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n",
+        ),
+    };
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves the current stats as JSON at `/stats`, in the `--json` report's
+/// format, from a task of its own. Each connection gets its own task so a
+/// slow client can't hold up the others.
+pub fn spawn(
+    listener: TcpListener,
+    stats: Arc<Mutex<Vec<PingStats<'static>>>>,
+    config: Arc<Config>,
+    started: Instant,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    log::warn!("failed to accept an API connection: {}", err);
+                    continue;
+                }
+            };
+            let stats = Arc::clone(&stats);
+            let config = Arc::clone(&config);
+            tokio::spawn(async move {
+                if let Err(err) = handle(stream, &stats, &config, started).await {
+                    log::debug!("API connection failed: {}", err);
+                }
+            });
+        }
+    })
+}
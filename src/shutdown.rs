@@ -0,0 +1,73 @@
+use std::io;
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+/// The signals that stop a headless run cleanly: SIGINT from Ctrl+C and
+/// SIGTERM from `kill`, systemd or a container runtime. Either way the run
+/// ends as with `--run-for`, so reports and checkpoints are still written.
+pub struct Shutdown {
+    #[cfg(unix)]
+    interrupt: Signal,
+    #[cfg(unix)]
+    terminate: Signal,
+}
+
+impl Shutdown {
+    /// Starts catching the signals. From here on they no longer kill the
+    /// process, and one that arrives before `recv` is called isn't missed.
+    pub fn listen() -> io::Result<Shutdown> {
+        Ok(Shutdown {
+            #[cfg(unix)]
+            interrupt: signal(SignalKind::interrupt())?,
+            #[cfg(unix)]
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    /// Waits for a signal, returning its name for the log.
+    #[cfg(unix)]
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+        }
+    }
+
+    /// Waits for Ctrl+C, the only signal outside Unix.
+    #[cfg(not(unix))]
+    pub async fn recv(&mut self) -> &'static str {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        "Ctrl+C"
+    }
+}
+
+/// Held by tests that signal the test process, which every listener in it
+/// hears, so they don't catch each other's signals.
+#[cfg(all(test, unix))]
+pub static SIGNALS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn catches_sigterm_and_sigint() {
+        let _signals = SIGNALS.lock().await;
+        let mut shutdown = Shutdown::listen().unwrap();
+        for (flag, name) in [("-TERM", "SIGTERM"), ("-INT", "SIGINT")] {
+            let sent = Command::new("kill")
+                .args([flag, &std::process::id().to_string()])
+                .status()
+                .unwrap();
+            assert!(sent.success());
+            let received = timeout(Duration::from_secs(5), shutdown.recv()).await;
+            assert_eq!(received, Ok(name));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, MissedTickBehavior};
+
+use crate::config::Config;
+use crate::export;
+use crate::screenshot::file_stamp;
+use crate::stats::PingStats;
+
+const PREFIX: &str = "pong-snapshot-";
+const EXTENSION: &str = ".json";
+
+/// Deletes the oldest snapshots in `dir` until at most `keep` are left. Only
+/// files named like snapshots are touched; their timestamps sort by age.
+fn prune(dir: &Path, keep: usize) -> io::Result<()> {
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(EXTENSION))
+        })
+        .collect();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(keep);
+    for path in &snapshots[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Writes the stats, in the `--json` report's format, to a new timestamped
+/// file in `dir` every `--snapshot-interval`, keeping the newest
+/// `--snapshot-keep` files.
+pub fn spawn(
+    dir: PathBuf,
+    stats: Arc<Mutex<Vec<PingStats<'static>>>>,
+    config: Arc<Config>,
+    started: Instant,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let period = config.snapshot_interval;
+        let mut ticker = interval_at((Instant::now() + period).into(), period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            let report = export::json_report(&stats.lock().await, &config, started.elapsed());
+            let path = dir.join(format!(
+                "{}{}{}",
+                PREFIX,
+                file_stamp(SystemTime::now()),
+                EXTENSION
+            ));
+            let result = fs::write(&path, report).and_then(|()| prune(&dir, config.snapshot_keep));
+            if let Err(err) = result {
+                log::warn!("failed to write snapshot {}: {}", path.display(), err);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_keeps_the_newest_snapshots_only() {
+        let dir = std::env::temp_dir().join(format!("pong-snapshots-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for stamp in [
+            "2024-05-01T00-02-00",
+            "2024-05-01T00-00-00",
+            "2024-05-01T00-01-00",
+        ] {
+            fs::write(dir.join(format!("{}{}{}", PREFIX, stamp, EXTENSION)), "{}").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        prune(&dir, 2).unwrap();
+        let mut left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            left,
+            [
+                "notes.txt",
+                "pong-snapshot-2024-05-01T00-01-00.json",
+                "pong-snapshot-2024-05-01T00-02-00.json",
+            ]
+        );
+    }
+}
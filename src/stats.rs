@@ -0,0 +1,1036 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use statrs::statistics::Statistics;
+
+use crate::json::{self, Value};
+use crate::ping::{PingError, Probe};
+
+/// How percentiles are read from the sorted sample window.
+///
+/// The default is inclusive nearest-rank: the P-th percentile is the smallest
+/// sample such that at least P% of the window is less than or equal to it. It
+/// always returns an observed latency, but with fewer than 20 samples both P95
+/// and P99 are the window's max. Linear interpolation between the two closest
+/// ranks (as in NumPy's default or Excel's `PERCENTILE.INC`) gives smoother
+/// values on small windows at the cost of reporting latencies never observed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PercentileMethod {
+    #[default]
+    NearestRank,
+    Linear,
+}
+
+impl FromStr for PercentileMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest-rank" => Ok(PercentileMethod::NearestRank),
+            "linear" => Ok(PercentileMethod::Linear),
+            _ => Err(format!(
+                "invalid percentile method '{}', expected nearest-rank or linear",
+                s
+            )),
+        }
+    }
+}
+
+/// Settings that change how samples are aggregated.
+#[derive(Debug, Clone, Copy)]
+pub struct StatsOptions {
+    pub percentile_method: PercentileMethod,
+    /// Weight of the newest sample in the exponentially-weighted average.
+    pub ewma_alpha: f64,
+    /// How far back the timestamped history used for recommendations goes.
+    pub history_window: Duration,
+    /// Keep the samples from this long ago in the statistics window instead
+    /// of the last `WINDOW_SAMPLES`.
+    pub window: Option<Duration>,
+    /// A sample at least this many times the window's average counts as a
+    /// spike, with `--spike-blink`.
+    pub spike_factor: Option<f64>,
+    /// How many of a region's first samples to drop as unrepresentative.
+    pub discard_warmup: u64,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        StatsOptions {
+            percentile_method: PercentileMethod::default(),
+            ewma_alpha: 0.2,
+            history_window: Duration::from_secs(300),
+            window: None,
+            spike_factor: None,
+            discard_warmup: 0,
+        }
+    }
+}
+
+/// Returns the `p`-th percentile (0–100) of a non-empty, ascending slice.
+pub fn percentile(sorted: &[f64], p: f64, method: PercentileMethod) -> f64 {
+    let len = sorted.len();
+    match method {
+        PercentileMethod::NearestRank => {
+            let rank = (p / 100.0 * len as f64).ceil() as usize;
+            sorted[rank.clamp(1, len) - 1]
+        }
+        PercentileMethod::Linear => {
+            let position = p / 100.0 * (len - 1) as f64;
+            let lower = position.floor() as usize;
+            let upper = position.ceil() as usize;
+            sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PingStats<'a> {
+    pub region: &'a str,
+    /// Successful latencies in the window with when they were measured, in
+    /// milliseconds.
+    latencies: VecDeque<(Instant, f64)>,
+    /// Whether each recent attempt succeeded, over the same window.
+    outcomes: VecDeque<(Instant, bool)>,
+    pub samples: u64,
+    /// Exponentially-weighted moving average, updated on every sample.
+    ewma: Option<f64>,
+    /// Timestamped outcomes over the last `history_window`, in milliseconds.
+    history: VecDeque<(Instant, Option<f64>)>,
+    /// Why the most recent attempt failed, cleared by the next success.
+    pub last_error: Option<PingError>,
+    /// Retries the most recent successful sample needed.
+    pub retries: Option<u32>,
+    /// Response size of the most recent successful sample, when measured.
+    pub last_bytes: Option<u64>,
+    /// When the most recent sample, failed or not, arrived.
+    last_sample: Option<Instant>,
+    /// When the current run of consecutive failures began, if in one.
+    outage_start: Option<Instant>,
+    /// The longest run of failures this session, from its first failure to
+    /// the success that ended it. Unlike the windows above, this is never
+    /// trimmed.
+    longest_outage: Option<Duration>,
+    /// The lowest and highest latency this session, in milliseconds. `min()`
+    /// and `max()` only cover the window.
+    session_min: Option<f64>,
+    session_max: Option<f64>,
+    /// The first successful latency, usually paid on a cold connection. Kept
+    /// after it leaves the window, for comparing against steady state.
+    first: Option<f64>,
+    /// When the most recent spike arrived, with `spike_factor` set.
+    last_spike: Option<Instant>,
+    /// Samples dropped so far under `discard_warmup`, failures included.
+    discarded: u64,
+    options: StatsOptions,
+}
+
+struct Percentiles {
+    p25: f64,
+    p50: f64,
+    p75: f64,
+    p90: f64,
+    p95: f64,
+    p99: f64,
+}
+
+/// The number under `key` in a checkpoint, with a missing key or `null` as
+/// `None`.
+fn optional_number(saved: &Value, key: &str) -> Result<Option<f64>, String> {
+    match saved.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(n)) => Ok(Some(*n)),
+        Some(_) => Err(format!("{} must be a number", key)),
+    }
+}
+
+/// The `[age_ms, value]` pairs under `key` in a checkpoint, oldest first.
+fn timed_entries<'v>(saved: &'v Value, key: &str) -> Result<Vec<(f64, &'v Value)>, String> {
+    let Some(Value::Array(entries)) = saved.get(key) else {
+        return Err(format!("missing {}", key));
+    };
+    entries
+        .iter()
+        .map(|entry| match entry {
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::Number(age), value] => Ok((*age, value)),
+                _ => Err(format!("{} must hold [age, value] pairs", key)),
+            },
+            _ => Err(format!("{} must hold [age, value] pairs", key)),
+        })
+        .collect()
+}
+
+/// How many recent samples the statistics cover unless windowed by time.
+pub const WINDOW_SAMPLES: usize = 100;
+
+/// Drops entries that have left the window: those older than `span` before
+/// `now` when windowing by time, or else all but the last `WINDOW_SAMPLES`.
+fn trim_window<T>(window: &mut VecDeque<(Instant, T)>, now: Instant, span: Option<Duration>) {
+    match span {
+        Some(span) => {
+            while window
+                .front()
+                .is_some_and(|&(at, _)| now.duration_since(at) > span)
+            {
+                window.pop_front();
+            }
+        }
+        None => {
+            let excess = window.len().saturating_sub(WINDOW_SAMPLES);
+            window.drain(..excess);
+        }
+    }
+}
+
+impl<'a> PingStats<'a> {
+    pub fn new(region: &'a str, options: StatsOptions) -> Self {
+        PingStats {
+            region,
+            latencies: VecDeque::new(),
+            outcomes: VecDeque::new(),
+            samples: 0,
+            ewma: None,
+            history: VecDeque::new(),
+            last_error: None,
+            retries: None,
+            last_bytes: None,
+            last_sample: None,
+            outage_start: None,
+            longest_outage: None,
+            session_min: None,
+            session_max: None,
+            first: None,
+            last_spike: None,
+            discarded: 0,
+            options,
+        }
+    }
+
+    /// Forgets every sample and session-wide record, as if the region had
+    /// just been added.
+    pub fn reset(&mut self) {
+        *self = PingStats::new(self.region, self.options);
+    }
+
+    /// Everything but the options as a JSON object, for `--resume`
+    /// checkpoints. Times are written as milliseconds before `now`, since an
+    /// `Instant` means nothing to another process.
+    pub fn checkpoint(&self, now: Instant) -> String {
+        let age = |at: Instant| {
+            json::number(Some(
+                now.saturating_duration_since(at).as_secs_f64() * 1000.0,
+            ))
+        };
+        let list = |entries: Vec<String>| format!("[{}]", entries.join(","));
+        let latencies = self
+            .latencies
+            .iter()
+            .map(|&(at, ms)| format!("[{},{}]", age(at), json::number(Some(ms))))
+            .collect();
+        let outcomes = self
+            .outcomes
+            .iter()
+            .map(|&(at, ok)| format!("[{},{}]", age(at), ok))
+            .collect();
+        let history = self
+            .history
+            .iter()
+            .map(|&(at, ms)| format!("[{},{}]", age(at), json::number(ms)))
+            .collect();
+        let fields = [
+            ("region", json::string(self.region)),
+            ("samples", self.samples.to_string()),
+            ("ewma", json::number(self.ewma)),
+            ("latencies", list(latencies)),
+            ("outcomes", list(outcomes)),
+            ("history", list(history)),
+            (
+                "last_error",
+                self.last_error
+                    .map_or("null".to_string(), |err| json::string(err.name())),
+            ),
+            ("retries", json::number(self.retries.map(f64::from))),
+            (
+                "last_bytes",
+                json::number(self.last_bytes.map(|b| b as f64)),
+            ),
+            (
+                "last_sample",
+                self.last_sample.map_or("null".to_string(), age),
+            ),
+            (
+                "outage_start",
+                self.outage_start.map_or("null".to_string(), age),
+            ),
+            (
+                "longest_outage_ms",
+                json::number(self.longest_outage.map(|o| o.as_secs_f64() * 1000.0)),
+            ),
+            ("session_min", json::number(self.session_min)),
+            ("session_max", json::number(self.session_max)),
+            ("first", json::number(self.first)),
+            (
+                "last_spike",
+                self.last_spike.map_or("null".to_string(), age),
+            ),
+        ];
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("{}:{}", json::string(key), value))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// Replaces the state with one written by `checkpoint`, `since_saved`
+    /// after it was written, so the gap shows as time passing. Entries older
+    /// than an `Instant` can reach are dropped, and the windows are trimmed
+    /// again under the current options. On error the state is left as it was.
+    pub fn restore(
+        &mut self,
+        saved: &Value,
+        now: Instant,
+        since_saved: Duration,
+    ) -> Result<(), String> {
+        let at = |age_ms: f64| {
+            Duration::try_from_secs_f64(age_ms / 1000.0)
+                .ok()
+                .and_then(|age| now.checked_sub(since_saved + age))
+        };
+        let instant = |key: &str| optional_number(saved, key).map(|age| age.and_then(at));
+        let mut restored = PingStats::new(self.region, self.options);
+
+        for (age, ms) in timed_entries(saved, "latencies")? {
+            let ms = ms.as_f64().ok_or("latencies must be numbers")?;
+            restored.latencies.extend(at(age).map(|at| (at, ms)));
+        }
+        for (age, ok) in timed_entries(saved, "outcomes")? {
+            let Value::Bool(ok) = *ok else {
+                return Err("outcomes must be true or false".to_string());
+            };
+            restored.outcomes.extend(at(age).map(|at| (at, ok)));
+        }
+        for (age, ms) in timed_entries(saved, "history")? {
+            let ms = match ms {
+                Value::Null => None,
+                Value::Number(ms) => Some(*ms),
+                _ => return Err("history must be numbers or null".to_string()),
+            };
+            restored.history.extend(at(age).map(|at| (at, ms)));
+        }
+        trim_window(&mut restored.latencies, now, self.options.window);
+        trim_window(&mut restored.outcomes, now, self.options.window);
+        let history_window = self.options.history_window;
+        restored
+            .history
+            .retain(|&(at, _)| now.duration_since(at) <= history_window);
+
+        restored.samples = optional_number(saved, "samples")?.ok_or("missing samples")? as u64;
+        restored.ewma = optional_number(saved, "ewma")?;
+        restored.last_error = saved
+            .get("last_error")
+            .and_then(Value::as_str)
+            .map(PingError::from_name);
+        restored.retries = optional_number(saved, "retries")?.map(|r| r as u32);
+        restored.last_bytes = optional_number(saved, "last_bytes")?.map(|b| b as u64);
+        restored.last_sample = instant("last_sample")?;
+        restored.outage_start = instant("outage_start")?;
+        restored.longest_outage = optional_number(saved, "longest_outage_ms")?
+            .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok());
+        restored.session_min = optional_number(saved, "session_min")?;
+        restored.session_max = optional_number(saved, "session_max")?;
+        restored.first = optional_number(saved, "first")?;
+        restored.last_spike = instant("last_spike")?;
+        *self = restored;
+        Ok(())
+    }
+
+    /// Counts a sample towards `discard_warmup` and reports whether it is to
+    /// be dropped.
+    fn discard_warmup(&mut self) -> bool {
+        let discard = self.discarded < self.options.discard_warmup;
+        if discard {
+            self.discarded += 1;
+        }
+        discard
+    }
+
+    /// How many samples were dropped under `--discard-warmup`.
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    pub fn add_probe(&mut self, probe: Probe) {
+        // A dropped sample leaves no trace, not even its error.
+        if self.discard_warmup() {
+            return;
+        }
+        self.last_error = probe.result.err();
+        if probe.result.is_ok() {
+            self.retries = Some(probe.retries);
+            self.last_bytes = probe.bytes;
+        }
+        self.add_latency(probe.result.ok());
+    }
+
+    pub fn add_latency(&mut self, latency: Option<Duration>) {
+        self.add_latency_at(latency, Instant::now());
+    }
+
+    /// Records a sample as if it arrived at `now`.
+    pub fn add_latency_at(&mut self, latency: Option<Duration>, now: Instant) {
+        if self.discard_warmup() {
+            return;
+        }
+        self.history
+            .push_back((now, latency.map(|lat| lat.as_secs_f64() * 1000.0)));
+        while let Some(&(at, _)) = self.history.front() {
+            if now.duration_since(at) <= self.options.history_window {
+                break;
+            }
+            self.history.pop_front();
+        }
+
+        let outage_start = match latency {
+            Some(_) => self.outage_start.take(),
+            None => Some(*self.outage_start.get_or_insert(now)),
+        };
+        if let Some(start) = outage_start {
+            let outage = now.duration_since(start);
+            self.longest_outage = Some(self.longest_outage.map_or(outage, |o| o.max(outage)));
+        }
+
+        self.last_sample = Some(now);
+        self.outcomes.push_back((now, latency.is_some()));
+        trim_window(&mut self.outcomes, now, self.options.window);
+        let ms = latency.map(|lat| lat.as_secs_f64() * 1000.0);
+        // Compared against the average before this sample joins it, so one
+        // spike can't hide itself by pulling the average up.
+        if let (Some(ms), Some(avg), Some(factor)) = (ms, self.avg(), self.options.spike_factor) {
+            if ms >= avg * factor {
+                self.last_spike = Some(now);
+            }
+        }
+        if let Some(ms) = ms {
+            self.latencies.push_back((now, ms));
+        }
+        // Trimmed on failures too, so a failing region's latencies age out of
+        // a time window like its outcomes do.
+        trim_window(&mut self.latencies, now, self.options.window);
+        if let Some(ms) = ms {
+            self.samples += 1;
+            self.first.get_or_insert(ms);
+            self.session_min = Some(self.session_min.map_or(ms, |min| min.min(ms)));
+            self.session_max = Some(self.session_max.map_or(ms, |max| max.max(ms)));
+            let alpha = self.options.ewma_alpha;
+            self.ewma = Some(
+                self.ewma
+                    .map_or(ms, |ewma| alpha * ms + (1.0 - alpha) * ewma),
+            );
+        }
+    }
+
+    /// The raw latency samples in the window, oldest first, in milliseconds.
+    pub fn latencies(&self) -> impl Iterator<Item = f64> + '_ {
+        self.latencies.iter().map(|&(_, ms)| ms)
+    }
+
+    /// How many latencies the window holds out of the `WINDOW_SAMPLES` it
+    /// keeps, or `None` when windowing by time, which has no fixed size.
+    pub fn window_fill(&self) -> Option<(usize, usize)> {
+        match self.options.window {
+            Some(_) => None,
+            None => Some((self.latencies.len(), WINDOW_SAMPLES)),
+        }
+    }
+
+    /// Buckets the window into `bins` equal-width bins between its min and
+    /// max, returning each bin's lower bound and sample count.
+    pub fn histogram(&self, bins: usize) -> Vec<(f64, u64)> {
+        let (Some(min), Some(max)) = (self.min(), self.max()) else {
+            return Vec::new();
+        };
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0u64; bins];
+        for ms in self.latencies() {
+            let bin = if width > 0.0 {
+                (((ms - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (min + width * i as f64, count))
+            .collect()
+    }
+
+    /// Fraction of attempts in the window that failed.
+    pub fn loss(&self) -> Option<f64> {
+        if self.outcomes.is_empty() {
+            return None;
+        }
+        let failed = self.outcomes.iter().filter(|&&(_, ok)| !ok).count();
+        Some(failed as f64 / self.outcomes.len() as f64)
+    }
+
+    /// The longest outage so far, counting an ongoing one up to its latest
+    /// failure, or `None` if no attempt has failed.
+    pub fn longest_outage(&self) -> Option<Duration> {
+        self.longest_outage
+    }
+
+    /// Whether every attempt over a full window failed: the last
+    /// `WINDOW_SAMPLES` of them, or with a time window, an outage lasting at
+    /// least its length. A region that only just started failing, or has
+    /// barely been tried, doesn't count yet.
+    pub fn is_unreachable(&self) -> bool {
+        let Some(start) = self.outage_start else {
+            return false;
+        };
+        match self.options.window {
+            Some(span) => self
+                .outcomes
+                .back()
+                .is_some_and(|&(at, _)| at.duration_since(start) >= span),
+            None => {
+                self.outcomes.len() >= WINDOW_SAMPLES && self.outcomes.iter().all(|&(_, ok)| !ok)
+            }
+        }
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.latencies().reduce(f64::min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.latencies().reduce(f64::max)
+    }
+
+    /// When the most recent sample arrived, if any has.
+    pub fn last_sample(&self) -> Option<Instant> {
+        self.last_sample
+    }
+
+    /// When the most recent spike arrived, if any has since `--spike-blink`
+    /// started watching.
+    pub fn last_spike(&self) -> Option<Instant> {
+        self.last_spike
+    }
+
+    /// The first successful latency of the session, in milliseconds.
+    pub fn first(&self) -> Option<f64> {
+        self.first
+    }
+
+    pub fn session_min(&self) -> Option<f64> {
+        self.session_min
+    }
+
+    pub fn session_max(&self) -> Option<f64> {
+        self.session_max
+    }
+
+    pub fn avg(&self) -> Option<f64> {
+        if self.latencies.is_empty() {
+            None
+        } else {
+            Some(self.latencies().mean())
+        }
+    }
+
+    pub fn stddev(&self) -> Option<f64> {
+        if self.latencies.len() > 1 {
+            Some(self.latencies().std_dev())
+        } else {
+            None
+        }
+    }
+
+    /// Approximate throughput of the most recent sample in bytes per second:
+    /// its response size over its latency, which includes the round trip.
+    pub fn throughput(&self) -> Option<f64> {
+        let bytes = self.last_bytes? as f64;
+        let ms = self.last().filter(|&ms| ms > 0.0)?;
+        Some(bytes / (ms / 1000.0))
+    }
+
+    /// Standard error of the mean (stddev / √n) over the window, which shrinks
+    /// as samples accumulate and the average becomes more trustworthy.
+    pub fn sem(&self) -> Option<f64> {
+        self.stddev()
+            .map(|stddev| stddev / (self.latencies.len() as f64).sqrt())
+    }
+
+    /// Coefficient of variation (stddev / avg); lower means more stable.
+    pub fn cv(&self) -> Option<f64> {
+        match (self.stddev(), self.avg()) {
+            (Some(stddev), Some(avg)) if avg > 0.0 => Some(stddev / avg),
+            _ => None,
+        }
+    }
+
+    /// Median latency of the samples in the window.
+    pub fn median(&self) -> Option<f64> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.latencies().collect();
+        sorted.sort_by(f64::total_cmp);
+        Some(percentile(&sorted, 50.0, self.options.percentile_method))
+    }
+
+    /// Median absolute deviation: the median distance of the window's
+    /// samples from their median. Like the IQR it shrugs off outliers, so a
+    /// few timeouts-turned-retries don't swamp it the way they do the stddev.
+    pub fn mad(&self) -> Option<f64> {
+        let median = self.median()?;
+        let mut deviations: Vec<f64> = self.latencies().map(|ms| (ms - median).abs()).collect();
+        deviations.sort_by(f64::total_cmp);
+        Some(percentile(
+            &deviations,
+            50.0,
+            self.options.percentile_method,
+        ))
+    }
+
+    /// Median latency over the history window.
+    pub fn recent_median(&self) -> Option<f64> {
+        let mut sorted: Vec<f64> = self.history.iter().filter_map(|&(_, ms)| ms).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        Some(percentile(&sorted, 50.0, self.options.percentile_method))
+    }
+
+    /// Median latency of the history samples taken from `from` up to, but
+    /// not including, `to`.
+    pub fn median_between(&self, from: Instant, to: Instant) -> Option<f64> {
+        let mut sorted: Vec<f64> = self
+            .history
+            .iter()
+            .filter(|&&(at, _)| at >= from && at < to)
+            .filter_map(|&(_, ms)| ms)
+            .collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        Some(percentile(&sorted, 50.0, self.options.percentile_method))
+    }
+
+    /// Fraction of attempts over the history window that failed.
+    pub fn recent_loss(&self) -> Option<f64> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let failed = self.history.iter().filter(|(_, ms)| ms.is_none()).count();
+        Some(failed as f64 / self.history.len() as f64)
+    }
+
+    pub fn ewma(&self) -> Option<f64> {
+        self.ewma
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.latencies.back().map(|&(_, ms)| ms)
+    }
+
+    /// Sorts the window once and returns every percentile the table shows.
+    fn calculate_percentiles_efficient(&self) -> Option<Percentiles> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.latencies().collect();
+        sorted.sort_by(f64::total_cmp);
+        let at = |p| percentile(&sorted, p, self.options.percentile_method);
+        Some(Percentiles {
+            p25: at(25.0),
+            p50: at(50.0),
+            p75: at(75.0),
+            p90: at(90.0),
+            p95: at(95.0),
+            p99: at(99.0),
+        })
+    }
+
+    pub fn p95(&self) -> Option<f64> {
+        self.calculate_percentiles_efficient().map(|p| p.p95)
+    }
+
+    pub fn p99(&self) -> Option<f64> {
+        self.calculate_percentiles_efficient().map(|p| p.p99)
+    }
+
+    /// P50, P90, P95 and P99 from a single sort of the window.
+    pub fn tail(&self) -> Option<[f64; 4]> {
+        self.calculate_percentiles_efficient()
+            .map(|p| [p.p50, p.p90, p.p95, p.p99])
+    }
+
+    /// Interquartile range (P75 − P25), a spread measure that, unlike the
+    /// standard deviation, ignores outliers.
+    pub fn iqr(&self) -> Option<f64> {
+        self.calculate_percentiles_efficient()
+            .map(|p| p.p75 - p.p25)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_from(latencies: &[f64], method: PercentileMethod) -> PingStats<'static> {
+        let options = StatsOptions {
+            percentile_method: method,
+            ..StatsOptions::default()
+        };
+        let mut stats = PingStats::new("test", options);
+        for &ms in latencies {
+            stats.add_latency(Some(Duration::from_secs_f64(ms / 1000.0)));
+        }
+        stats
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("expected a value");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn summary_of_known_sequence() {
+        let stats = stats_from(&[30.0, 10.0, 40.0, 20.0], PercentileMethod::NearestRank);
+        assert_close(stats.min(), 10.0);
+        assert_close(stats.max(), 40.0);
+        assert_close(stats.avg(), 25.0);
+        assert_close(stats.stddev(), (500.0f64 / 3.0).sqrt());
+        assert_close(stats.sem(), (500.0f64 / 3.0).sqrt() / 2.0);
+        assert_close(stats.p95(), 40.0);
+        assert_close(stats.p99(), 40.0);
+        assert_close(stats.last(), 20.0);
+        assert_eq!(stats.samples, 4);
+    }
+
+    #[test]
+    fn time_window_ages_out_old_samples() {
+        let options = StatsOptions {
+            window: Some(Duration::from_secs(60)),
+            ..StatsOptions::default()
+        };
+        let mut stats = PingStats::new("test", options);
+        let start = Instant::now();
+        stats.add_latency_at(Some(Duration::from_millis(100)), start);
+        stats.add_latency_at(None, start + Duration::from_secs(30));
+        stats.add_latency_at(
+            Some(Duration::from_millis(20)),
+            start + Duration::from_secs(50),
+        );
+        assert_close(stats.avg(), 60.0);
+        assert_close(stats.loss(), 1.0 / 3.0);
+
+        // Only the samples from the last minute count, however few there are.
+        stats.add_latency_at(
+            Some(Duration::from_millis(40)),
+            start + Duration::from_secs(95),
+        );
+        assert_close(stats.avg(), 30.0);
+        assert_close(stats.loss(), 0.0);
+        assert_eq!(stats.samples, 3);
+    }
+
+    #[test]
+    fn spikes_are_measured_against_the_earlier_average() {
+        let options = StatsOptions {
+            spike_factor: Some(3.0),
+            ..StatsOptions::default()
+        };
+        let mut stats = PingStats::new("test", options);
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        // The first sample has no average to spike against.
+        stats.add_latency_at(Some(ms(100)), start);
+        stats.add_latency_at(Some(ms(10)), start);
+        stats.add_latency_at(Some(ms(10)), start);
+        assert_eq!(stats.last_spike(), None);
+
+        // Over 3× the average of 40 ms, though not 3× the 62.5 ms average it
+        // makes once included.
+        let spike = start + Duration::from_secs(1);
+        stats.add_latency_at(Some(ms(130)), spike);
+        assert_eq!(stats.last_spike(), Some(spike));
+
+        // Failures and ordinary samples leave the last spike alone.
+        stats.add_latency_at(None, spike + Duration::from_secs(1));
+        stats.add_latency_at(Some(ms(50)), spike + Duration::from_secs(2));
+        assert_eq!(stats.last_spike(), Some(spike));
+    }
+
+    #[test]
+    fn warmup_samples_are_discarded_entirely() {
+        let options = StatsOptions {
+            discard_warmup: 2,
+            ..StatsOptions::default()
+        };
+        let mut stats = PingStats::new("test", options);
+        stats.add_probe(Probe {
+            result: Err(PingError::Dns),
+            retries: 3,
+            bytes: None,
+        });
+        stats.add_latency(Some(Duration::from_millis(500)));
+        assert_eq!(stats.discarded(), 2);
+        assert_eq!(stats.samples, 0);
+        assert_eq!(stats.loss(), None);
+        assert_eq!(stats.last_error, None);
+
+        stats.add_latency(Some(Duration::from_millis(20)));
+        stats.add_latency(Some(Duration::from_millis(40)));
+        assert_eq!(stats.discarded(), 2);
+        assert_eq!(stats.samples, 2);
+        assert_close(stats.max(), 40.0);
+        assert_close(stats.first(), 20.0);
+    }
+
+    #[test]
+    fn reset_forgets_window_and_session_records() {
+        let mut stats = stats_from(&[10.0, 200.0], PercentileMethod::NearestRank);
+        stats.add_latency(None);
+        stats.reset();
+        assert_eq!(stats.avg(), None);
+        assert_eq!(stats.p95(), None);
+        assert_eq!(stats.loss(), None);
+        assert_eq!(stats.session_max(), None);
+        assert_eq!(stats.longest_outage(), None);
+        assert_eq!(stats.samples, 0);
+
+        stats.add_latency(Some(Duration::from_millis(50)));
+        assert_close(stats.max(), 50.0);
+        assert_close(stats.p95(), 50.0);
+        assert_close(stats.first(), 50.0);
+        assert_close(stats.ewma(), 50.0);
+    }
+
+    #[test]
+    fn summary_reflects_samples_added_after_reading() {
+        let mut stats = stats_from(&[10.0, 20.0, 30.0], PercentileMethod::NearestRank);
+        assert_close(stats.avg(), 20.0);
+        assert_close(stats.p95(), 30.0);
+
+        stats.add_latency(Some(Duration::from_millis(60)));
+        assert_close(stats.max(), 60.0);
+        assert_close(stats.avg(), 30.0);
+        assert_close(stats.p95(), 60.0);
+        assert_close(stats.last(), 60.0);
+
+        // A failure changes the loss but none of the latency statistics.
+        stats.add_latency(None);
+        assert_close(stats.avg(), 30.0);
+        assert_close(stats.loss(), 0.2);
+    }
+
+    #[test]
+    fn window_keeps_the_latest_hundred_samples() {
+        let latencies: Vec<f64> = (1..=150).map(f64::from).collect();
+        let mut stats = stats_from(&latencies, PercentileMethod::NearestRank);
+        assert_eq!(stats.latencies().count(), 100);
+        assert_eq!(stats.samples, 150);
+        assert_close(stats.min(), 51.0);
+        assert_close(stats.max(), 150.0);
+        assert_close(stats.session_min(), 1.0);
+        assert_close(stats.first(), 1.0);
+        assert_close(stats.session_max(), 150.0);
+        assert_close(stats.avg(), 100.5);
+        assert_close(stats.p95(), 145.0);
+        assert_close(stats.p99(), 149.0);
+
+        for _ in 0..100 {
+            stats.add_latency(None);
+        }
+        assert_close(stats.loss(), 1.0);
+        assert!(stats.is_unreachable());
+        assert_close(stats.avg(), 100.5);
+    }
+
+    #[test]
+    fn percentiles_are_none_without_samples() {
+        let stats = stats_from(&[], PercentileMethod::NearestRank);
+        assert_eq!(stats.p95(), None);
+        assert_eq!(stats.p99(), None);
+    }
+
+    #[test]
+    fn single_sample_is_every_percentile() {
+        for method in [PercentileMethod::NearestRank, PercentileMethod::Linear] {
+            let stats = stats_from(&[42.0], method);
+            assert_close(stats.p95(), 42.0);
+            assert_close(stats.p99(), 42.0);
+        }
+    }
+
+    #[test]
+    fn nearest_rank_small_window_reports_max() {
+        let stats = stats_from(&[20.0, 10.0], PercentileMethod::NearestRank);
+        assert_close(stats.p95(), 20.0);
+        assert_close(stats.p99(), 20.0);
+
+        let stats = stats_from(&[5.0, 1.0, 3.0, 2.0, 4.0], PercentileMethod::NearestRank);
+        assert_close(stats.p95(), 5.0);
+        assert_close(stats.p99(), 5.0);
+    }
+
+    #[test]
+    fn nearest_rank_picks_observed_samples() {
+        let sorted: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 0.0, PercentileMethod::NearestRank), 1.0);
+        assert_eq!(
+            percentile(&sorted, 50.0, PercentileMethod::NearestRank),
+            5.0
+        );
+        assert_eq!(
+            percentile(&sorted, 51.0, PercentileMethod::NearestRank),
+            6.0
+        );
+        assert_eq!(
+            percentile(&sorted, 100.0, PercentileMethod::NearestRank),
+            10.0
+        );
+
+        let sorted: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(
+            percentile(&sorted, 95.0, PercentileMethod::NearestRank),
+            95.0
+        );
+        assert_eq!(
+            percentile(&sorted, 99.0, PercentileMethod::NearestRank),
+            99.0
+        );
+    }
+
+    #[test]
+    fn linear_interpolates_between_ranks() {
+        let stats = stats_from(&[20.0, 10.0], PercentileMethod::Linear);
+        assert_close(stats.p95(), 19.5);
+        assert_close(stats.p99(), 19.9);
+
+        let latencies: Vec<f64> = (1..=10).map(f64::from).collect();
+        let stats = stats_from(&latencies, PercentileMethod::Linear);
+        assert_close(stats.p95(), 9.55);
+        assert_close(stats.p99(), 9.91);
+    }
+
+    #[test]
+    fn longest_outage_spans_consecutive_failures() {
+        let mut stats = stats_from(&[10.0], PercentileMethod::NearestRank);
+        assert_eq!(stats.longest_outage(), None);
+
+        stats.add_latency(None);
+        std::thread::sleep(Duration::from_millis(20));
+        stats.add_latency(None);
+        stats.add_latency(Some(Duration::from_millis(10)));
+        let outage = stats.longest_outage().unwrap();
+        assert!(outage >= Duration::from_millis(20));
+
+        // A shorter outage later doesn't replace the record.
+        stats.add_latency(None);
+        stats.add_latency(Some(Duration::from_millis(10)));
+        assert_eq!(stats.longest_outage(), Some(outage));
+    }
+
+    #[test]
+    fn unreachable_takes_a_full_window_of_failures() {
+        let start = Instant::now();
+        let secs = |secs: u64| start + Duration::from_secs(secs);
+
+        // By sample count: a hundred failures, not just a few.
+        let mut stats = PingStats::new("test", StatsOptions::default());
+        stats.add_latency_at(Some(Duration::from_millis(10)), secs(0));
+        for i in 1..WINDOW_SAMPLES as u64 {
+            stats.add_latency_at(None, secs(i));
+            assert!(!stats.is_unreachable(), "after {} failures", i);
+        }
+        stats.add_latency_at(None, secs(WINDOW_SAMPLES as u64));
+        assert!(stats.is_unreachable());
+        stats.add_latency_at(Some(Duration::from_millis(10)), secs(101));
+        assert!(!stats.is_unreachable());
+
+        // By time: failures spanning the whole window, however few.
+        let options = StatsOptions {
+            window: Some(Duration::from_secs(60)),
+            ..StatsOptions::default()
+        };
+        let mut stats = PingStats::new("test", options);
+        stats.add_latency_at(Some(Duration::from_millis(10)), secs(0));
+        stats.add_latency_at(None, secs(10));
+        stats.add_latency_at(None, secs(40));
+        assert!(!stats.is_unreachable());
+        stats.add_latency_at(None, secs(70));
+        assert!(stats.is_unreachable());
+    }
+
+    #[test]
+    fn iqr_ignores_outliers() {
+        let latencies: Vec<f64> = (1..=8).map(f64::from).collect();
+        let stats = stats_from(&latencies, PercentileMethod::NearestRank);
+        assert_close(stats.iqr(), 4.0);
+
+        let mut with_outlier = latencies.clone();
+        with_outlier[7] = 1000.0;
+        let stats = stats_from(&with_outlier, PercentileMethod::NearestRank);
+        assert_close(stats.iqr(), 4.0);
+
+        let stats = stats_from(&latencies, PercentileMethod::Linear);
+        assert_close(stats.iqr(), 3.5);
+    }
+
+    #[test]
+    fn mad_ignores_outliers() {
+        let stats = stats_from(&[1.0, 2.0, 3.0, 4.0, 5.0], PercentileMethod::Linear);
+        assert_close(stats.mad(), 1.0);
+
+        let stats = stats_from(&[1.0, 2.0, 3.0, 4.0, 100.0], PercentileMethod::Linear);
+        assert_close(stats.mad(), 1.0);
+
+        assert_eq!(stats_from(&[], PercentileMethod::Linear).mad(), None);
+    }
+
+    #[test]
+    fn ewma_weights_recent_samples() {
+        let mut stats = stats_from(&[10.0], PercentileMethod::NearestRank);
+        assert_close(stats.ewma(), 10.0);
+
+        stats.add_latency(Some(Duration::from_millis(20)));
+        assert_close(stats.ewma(), 12.0);
+
+        stats.add_latency(None);
+        assert_close(stats.ewma(), 12.0);
+    }
+
+    #[test]
+    fn histogram_buckets_between_min_and_max() {
+        let stats = stats_from(
+            &[10.0, 12.0, 14.0, 19.0, 20.0],
+            PercentileMethod::NearestRank,
+        );
+        let histogram = stats.histogram(2);
+        assert_eq!(histogram.len(), 2);
+        assert_close(Some(histogram[0].0), 10.0);
+        assert_close(Some(histogram[1].0), 15.0);
+        assert_eq!(histogram[0].1, 3);
+        assert_eq!(histogram[1].1, 2);
+
+        let flat = stats_from(&[5.0, 5.0], PercentileMethod::NearestRank).histogram(4);
+        assert_eq!(flat.iter().map(|&(_, count)| count).sum::<u64>(), 2);
+        assert!(stats_from(&[], PercentileMethod::NearestRank)
+            .histogram(4)
+            .is_empty());
+    }
+}
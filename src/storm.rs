@@ -0,0 +1,257 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::stats::PingStats;
+use crate::ui::format_latency;
+
+/// Probes sent to each endpoint at once during a storm.
+pub const CONCURRENCY: usize = 4;
+
+/// Time between rounds of probes during a storm.
+pub const INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long a storm started with the `S` key lasts without `--storm`.
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Calm,
+    /// Waiting for `--storm`'s storm to start.
+    Scheduled {
+        start: Instant,
+    },
+    Raging {
+        start: Instant,
+        end: Instant,
+    },
+    /// Over, but waiting for as long again to see how latency recovers.
+    Settling {
+        start: Instant,
+        end: Instant,
+    },
+}
+
+/// When a storm ran, for comparing latency before, during and after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub start: Instant,
+    pub end: Instant,
+}
+
+/// What `Storm::poll` noticed since it was last called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Started,
+    Settled(Window),
+}
+
+/// A burst of load for seeing how latency responds to pressure: while it
+/// rages, every region is probed `CONCURRENCY` times at once every
+/// `INTERVAL` instead of on its usual schedule. Shared between the ping
+/// tasks, which ask whether it rages, and the UI, which starts it and polls
+/// for when to report.
+pub struct Storm {
+    duration: Duration,
+    state: Mutex<State>,
+}
+
+impl Storm {
+    /// A storm lasting `duration` once started, starting by itself at
+    /// `scheduled` if given.
+    pub fn new(duration: Duration, scheduled: Option<Instant>) -> Self {
+        let state = match scheduled {
+            Some(start) => State::Scheduled { start },
+            None => State::Calm,
+        };
+        Storm {
+            duration,
+            state: Mutex::new(state),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts a storm at `now` unless one is already under way. Returns
+    /// whether it started.
+    pub fn start(&self, now: Instant) -> bool {
+        let mut state = self.state();
+        if matches!(*state, State::Raging { .. } | State::Settling { .. }) {
+            return false;
+        }
+        *state = State::Raging {
+            start: now,
+            end: now + self.duration,
+        };
+        true
+    }
+
+    /// Whether the ping tasks should be storming at `now`. A storm stops on
+    /// time whether or not it has been polled since.
+    pub fn raging(&self, now: Instant) -> bool {
+        match *self.state() {
+            State::Scheduled { start } => now >= start && now < start + self.duration,
+            State::Raging { end, .. } => now < end,
+            State::Calm | State::Settling { .. } => false,
+        }
+    }
+
+    /// Moves the storm along as of `now`, returning when a scheduled one
+    /// starts or one has settled and can be reported on.
+    pub fn poll(&self, now: Instant) -> Option<Event> {
+        let mut state = self.state();
+        match *state {
+            State::Scheduled { start } if now >= start => {
+                *state = State::Raging {
+                    start,
+                    end: start + self.duration,
+                };
+                Some(Event::Started)
+            }
+            State::Raging { start, end } if now >= end => {
+                *state = State::Settling { start, end };
+                None
+            }
+            State::Settling { start, end } if now >= end + self.duration => {
+                *state = State::Calm;
+                Some(Event::Settled(Window { start, end }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A region's median latency over the storm's length before it, during it
+/// and after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub region: &'static str,
+    pub before: Option<f64>,
+    pub during: Option<f64>,
+    pub after: Option<f64>,
+}
+
+impl Delta {
+    /// One line for the log or stderr, e.g.
+    /// `us-east-1: 12.0 ms before, 30.1 ms during (+18.1), 12.5 ms after (+0.5)`.
+    pub fn line(&self, precision: usize) -> String {
+        let change = |value: Option<f64>| match (value, self.before) {
+            (Some(value), Some(before)) => format!(" ({:+.*})", precision, value - before),
+            _ => String::new(),
+        };
+        format!(
+            "{}: {} before, {} during{}, {} after{}",
+            self.region,
+            format_latency(self.before, precision),
+            format_latency(self.during, precision),
+            change(self.during),
+            format_latency(self.after, precision),
+            change(self.after)
+        )
+    }
+}
+
+impl Window {
+    /// Compares each region's median latency over the storm's length just
+    /// before it, during it and just after it. Needs the history window to
+    /// reach back to before the storm.
+    pub fn report(&self, stats: &[PingStats<'static>]) -> Vec<Delta> {
+        let length = self.end.duration_since(self.start);
+        stats
+            .iter()
+            .map(|stat| Delta {
+                region: stat.region,
+                before: self
+                    .start
+                    .checked_sub(length)
+                    .and_then(|from| stat.median_between(from, self.start)),
+                during: stat.median_between(self.start, self.end),
+                after: stat.median_between(self.end, self.end + length),
+            })
+            .collect()
+    }
+}
+
+/// The average change in median latency across the regions measured before
+/// and during the storm, for the footer.
+pub fn summary(deltas: &[Delta], precision: usize) -> String {
+    let mean_change = |during: bool| {
+        let changes: Vec<f64> = deltas
+            .iter()
+            .filter_map(|delta| {
+                let value = if during { delta.during } else { delta.after };
+                Some(value? - delta.before?)
+            })
+            .collect();
+        (!changes.is_empty()).then(|| changes.iter().sum::<f64>() / changes.len() as f64)
+    };
+    match (mean_change(true), mean_change(false)) {
+        (Some(during), Some(after)) => format!(
+            "storm: {:+.*} ms during, {:+.*} ms after",
+            precision, during, precision, after
+        ),
+        (Some(during), None) => format!("storm: {:+.*} ms during", precision, during),
+        _ => "storm: not enough samples to compare".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsOptions;
+
+    #[test]
+    fn scheduled_storm_rages_then_settles_once() {
+        let start = Instant::now() + Duration::from_secs(10);
+        let storm = Storm::new(Duration::from_secs(5), Some(start));
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert!(!storm.raging(start - Duration::from_secs(1)));
+        assert_eq!(storm.poll(start - Duration::from_secs(1)), None);
+        // The ping tasks see it start even before anything polls.
+        assert!(storm.raging(at(0)));
+        assert_eq!(storm.poll(at(1)), Some(Event::Started));
+        assert!(!storm.start(at(2)));
+
+        assert!(!storm.raging(at(5)));
+        assert_eq!(storm.poll(at(5)), None);
+        assert!(!storm.start(at(6)));
+        let window = Window { start, end: at(5) };
+        assert_eq!(storm.poll(at(10)), Some(Event::Settled(window)));
+        assert_eq!(storm.poll(at(11)), None);
+
+        assert!(storm.start(at(12)));
+        assert!(storm.raging(at(12)));
+    }
+
+    #[test]
+    fn report_compares_the_periods_around_the_storm() {
+        let start = Instant::now();
+        let secs = |secs: u64| start + Duration::from_secs(secs);
+        let mut stat = PingStats::new("test", StatsOptions::default());
+        for (at, ms) in [(1, 10), (3, 12), (4, 40), (6, 50), (7, 14), (8, 11)] {
+            stat.add_latency_at(Some(Duration::from_millis(ms)), secs(at));
+        }
+
+        let window = Window {
+            start: secs(3),
+            end: secs(6),
+        };
+        let deltas = window.report(&[stat]);
+        assert_eq!(
+            deltas,
+            [Delta {
+                region: "test",
+                before: Some(10.0),
+                during: Some(12.0),
+                after: Some(14.0),
+            }]
+        );
+        assert_eq!(
+            deltas[0].line(1),
+            "test: 10.0 ms before, 12.0 ms during (+2.0), 14.0 ms after (+4.0)"
+        );
+        assert_eq!(summary(&deltas, 1), "storm: +2.0 ms during, +4.0 ms after");
+    }
+}
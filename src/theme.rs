@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use ratatui::style::{Color, Modifier, Style};
+
+/// Built-in themes selectable with `--theme`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThemePreset {
+    #[default]
+    Default,
+    Mono,
+    HighContrast,
+}
+
+impl FromStr for ThemePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(ThemePreset::Default),
+            "mono" => Ok(ThemePreset::Mono),
+            "high-contrast" => Ok(ThemePreset::HighContrast),
+            _ => Err(format!(
+                "invalid theme '{}', expected default, mono or high-contrast",
+                s
+            )),
+        }
+    }
+}
+
+/// Styles used when rendering the table.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub border: Style,
+    pub header: Style,
+    pub region: Style,
+    pub value: Style,
+    pub good: Style,
+    pub bad: Style,
+    pub highlight: Style,
+    pub footer: Style,
+    /// Marker drawn in front of the selected row, for themes whose highlight
+    /// style alone may not be visible.
+    pub highlight_symbol: &'static str,
+    /// Whether colors are limited to the 16 basic ones, see `downsample`.
+    pub basic_colors: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::preset(ThemePreset::Default)
+    }
+}
+
+impl Theme {
+    pub fn preset(preset: ThemePreset) -> Self {
+        match preset {
+            ThemePreset::Default => Theme {
+                border: Style::default(),
+                header: Style::default().fg(Color::Cyan),
+                region: Style::default().fg(Color::White),
+                value: Style::default().fg(Color::Yellow),
+                good: Style::default().fg(Color::Green),
+                bad: Style::default().fg(Color::Red),
+                highlight: Style::default().bg(Color::DarkGray),
+                footer: Style::default().fg(Color::DarkGray),
+                highlight_symbol: "",
+                basic_colors: false,
+            },
+            ThemePreset::Mono => Theme {
+                border: Style::default(),
+                header: Style::default().add_modifier(Modifier::BOLD),
+                region: Style::default(),
+                value: Style::default(),
+                good: Style::default(),
+                bad: Style::default().add_modifier(Modifier::BOLD),
+                highlight: Style::default().add_modifier(Modifier::REVERSED),
+                footer: Style::default(),
+                highlight_symbol: "",
+                basic_colors: false,
+            },
+            ThemePreset::HighContrast => Theme {
+                border: Style::default().fg(Color::White),
+                header: Style::default()
+                    .fg(Color::LightCyan)
+                    .add_modifier(Modifier::BOLD),
+                region: Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+                value: Style::default().fg(Color::LightYellow),
+                good: Style::default()
+                    .fg(Color::LightGreen)
+                    .add_modifier(Modifier::BOLD),
+                bad: Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+                highlight: Style::default().fg(Color::Black).bg(Color::White),
+                footer: Style::default().fg(Color::White),
+                highlight_symbol: "",
+                basic_colors: false,
+            },
+        }
+    }
+
+    /// A theme with every style reset to the terminal default, for piped
+    /// output, dumb terminals and `NO_COLOR`.
+    pub fn plain() -> Self {
+        Theme {
+            border: Style::default(),
+            header: Style::default(),
+            region: Style::default(),
+            value: Style::default(),
+            good: Style::default(),
+            bad: Style::default(),
+            highlight: Style::default(),
+            footer: Style::default(),
+            highlight_symbol: "> ",
+            basic_colors: false,
+        }
+    }
+
+    /// Replaces 24-bit and 256-color values with the nearest of the 16 basic
+    /// colors, for terminals that can't show more.
+    pub fn downsample(&mut self) {
+        for style in [
+            &mut self.border,
+            &mut self.header,
+            &mut self.region,
+            &mut self.value,
+            &mut self.good,
+            &mut self.bad,
+            &mut self.highlight,
+            &mut self.footer,
+        ] {
+            style.fg = style.fg.map(ansi16);
+            style.bg = style.bg.map(ansi16);
+        }
+        self.basic_colors = true;
+    }
+
+    /// A color picked at draw time, downsampled like the theme's own.
+    pub fn color(&self, color: Color) -> Color {
+        if self.basic_colors {
+            ansi16(color)
+        } else {
+            color
+        }
+    }
+
+    /// Applies the `[theme]` section of the config file on top of this theme.
+    /// Each key takes a color name or `#rrggbb` value; `highlight` sets the
+    /// background of the selected row, every other key sets the foreground.
+    pub fn apply_overrides(&mut self, section: &HashMap<String, String>) -> Result<(), String> {
+        for (key, value) in section {
+            let color = Color::from_str(value)
+                .map_err(|_| format!("invalid color '{}' for theme.{}", value, key))?;
+            match key.as_str() {
+                "border" => self.border = self.border.fg(color),
+                "header" => self.header = self.header.fg(color),
+                "region" => self.region = self.region.fg(color),
+                "value" => self.value = self.value.fg(color),
+                "good" => self.good = self.good.fg(color),
+                "bad" => self.bad = self.bad.fg(color),
+                "highlight" => self.highlight = self.highlight.bg(color),
+                "footer" => self.footer = self.footer.fg(color),
+                _ => return Err(format!("unknown theme key '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the terminal can show 24-bit color. Most terminals advertise it
+/// through `COLORTERM`. On Windows, Windows Terminal sets `WT_SESSION`
+/// instead, while the legacy console only has the 16 console colors.
+pub fn supports_truecolor() -> bool {
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return true;
+    }
+    #[cfg(windows)]
+    {
+        std::env::var_os("WT_SESSION").is_some() && crossterm::ansi_support::supports_ansi()
+    }
+    #[cfg(not(windows))]
+    {
+        false
+    }
+}
+
+/// The basic colors with typical RGB values, in ANSI order.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The RGB value of a color from the 256-color palette.
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16[index as usize].1,
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = index - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// The RGB value a color is typically shown as, or `None` for the terminal's
+/// default color.
+pub fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Reset => None,
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::Indexed(index) => Some(indexed_rgb(index)),
+        named => ANSI16
+            .iter()
+            .find(|&&(c, _)| c == named)
+            .map(|&(_, rgb)| rgb),
+    }
+}
+
+fn ansi16(color: Color) -> Color {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index) => indexed_rgb(index),
+        other => return other,
+    };
+    let distance = |&(_, (cr, cg, cb)): &(Color, (u8, u8, u8))| {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    ANSI16
+        .iter()
+        .min_by_key(|entry| distance(entry))
+        .map_or(color, |&(c, _)| c)
+}
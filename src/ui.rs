@@ -0,0 +1,1629 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::{Alignment, Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{
+        BarChart, Block, Borders, Cell, Clear, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Sparkline, Table, TableState, Wrap,
+    },
+    Frame,
+};
+
+use crate::config::{Config, LayoutMode, SortKey};
+use crate::ping::{self, PingError};
+use crate::recommend::{score, Leader};
+use crate::regions;
+use crate::stats::{percentile, PingStats};
+use crate::{App, UNREACHABLE_INTERVAL};
+
+const PIN_MARKER: &str = "★ ";
+
+/// Height of the distribution panel, including its border.
+const HISTOGRAM_HEIGHT: u16 = 12;
+
+/// Reads one statistic of a region.
+type Metric = fn(&PingStats) -> Option<f64>;
+
+/// Metrics in the comparison panel, each read as lower is better.
+const COMPARED_METRICS: [(&str, Metric); 9] = [
+    ("Last", |stat| stat.last()),
+    ("Min", |stat| stat.min()),
+    ("Avg", |stat| stat.avg()),
+    ("Median", |stat| stat.median()),
+    ("Max", |stat| stat.max()),
+    ("Stddev", |stat| stat.stddev()),
+    ("P95", |stat| stat.p95()),
+    ("P99", |stat| stat.p99()),
+    ("Loss", |stat| stat.loss()),
+];
+
+/// Height of the comparison panel: a row per metric, its header and borders.
+const COMPARISON_HEIGHT: u16 = COMPARED_METRICS.len() as u16 + 3;
+
+/// Below this size the table can't show a single readable row, so a notice is
+/// drawn instead. The height covers the table's borders, header and one row,
+/// the recommendation panel and the footer.
+const MIN_WIDTH: u16 = 40;
+const MIN_STACKED_WIDTH: u16 = 20;
+const MIN_TABLE_HEIGHT: u16 = 4;
+const MIN_HEIGHT: u16 = MIN_TABLE_HEIGHT + 3 + 1;
+
+/// Below this width `--layout auto` switches to the stacked layout.
+const STACKED_BELOW_WIDTH: u16 = 60;
+
+pub const COLUMN_LABELS: [&str; 8] = [
+    "AWS Region",
+    "Last",
+    "Min",
+    "Avg",
+    "Max",
+    "Stddev",
+    "P95",
+    "P99",
+];
+
+/// Coefficients of variation below this are considered stable.
+const STABLE_CV: f64 = 0.1;
+
+/// Samples that needed at least this many retries are highlighted.
+const FLAKY_RETRIES: u32 = 2;
+
+/// Columns that can be appended to the default table with `--columns`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtraColumn {
+    Cv,
+    Ewma,
+    /// Retries the last successful sample needed; a region that keeps needing
+    /// them is flaky even when its latency looks fine.
+    Retries,
+    /// Interquartile range, a spread measure robust to outliers.
+    Iqr,
+    /// Change in average latency against the `--baseline` run.
+    Baseline,
+    /// Standard error of the mean, how far the average may be off.
+    Sem,
+    /// Median absolute deviation, a spread measure robust to outliers.
+    Mad,
+    /// Why the latest attempt failed, as a short code; blank once one
+    /// succeeds again.
+    Error,
+    /// The region's lowest latency ever seen, from the `--records` file
+    /// and this run.
+    Record,
+}
+
+impl ExtraColumn {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExtraColumn::Cv => "CV",
+            ExtraColumn::Ewma => "EWMA",
+            ExtraColumn::Retries => "Retries",
+            ExtraColumn::Iqr => "IQR",
+            ExtraColumn::Baseline => "Δ Baseline",
+            ExtraColumn::Sem => "SEM",
+            ExtraColumn::Mad => "MAD",
+            ExtraColumn::Error => "Error",
+            ExtraColumn::Record => "Record",
+        }
+    }
+
+    pub fn text(self, stat: &PingStats, config: &Config) -> String {
+        let sufficient = stat.samples >= config.min_samples;
+        match self {
+            ExtraColumn::Cv if !sufficient => INSUFFICIENT.to_string(),
+            ExtraColumn::Ewma if !sufficient => INSUFFICIENT.to_string(),
+            ExtraColumn::Iqr if !sufficient => INSUFFICIENT.to_string(),
+            ExtraColumn::Sem if !sufficient => INSUFFICIENT.to_string(),
+            ExtraColumn::Mad if !sufficient => INSUFFICIENT.to_string(),
+            ExtraColumn::Cv => format_ratio(stat.cv()),
+            ExtraColumn::Ewma => format_latency(stat.ewma(), config.precision),
+            ExtraColumn::Retries => stat
+                .retries
+                .map_or("--".to_string(), |retries| retries.to_string()),
+            ExtraColumn::Iqr => format_latency(stat.iqr(), config.precision),
+            ExtraColumn::Sem => format_latency(stat.sem(), config.precision),
+            ExtraColumn::Mad => format_latency(stat.mad(), config.precision),
+            ExtraColumn::Error => stat
+                .last_error
+                .map_or(String::new(), |err| err.code().to_string()),
+            ExtraColumn::Record => format_latency(
+                config
+                    .records
+                    .as_ref()
+                    .and_then(|records| records.record(stat)),
+                config.precision,
+            ),
+            ExtraColumn::Baseline => match baseline_delta(stat, config) {
+                BaselineDelta::New => "(new)".to_string(),
+                BaselineDelta::Change(delta) if sufficient => {
+                    format!("{:+.*} ms", config.precision, delta)
+                }
+                BaselineDelta::Change(_) => INSUFFICIENT.to_string(),
+                BaselineDelta::Unknown => "--".to_string(),
+            },
+        }
+    }
+
+    fn style(self, stat: &PingStats, config: &Config) -> Style {
+        let theme = &config.theme;
+        match self {
+            ExtraColumn::Cv => match stat.cv() {
+                Some(cv) if cv < STABLE_CV => theme.good,
+                Some(_) => theme.bad,
+                None => theme.value,
+            },
+            ExtraColumn::Ewma | ExtraColumn::Iqr | ExtraColumn::Sem | ExtraColumn::Mad => {
+                theme.value
+            }
+            ExtraColumn::Retries => match stat.retries {
+                Some(retries) if retries >= FLAKY_RETRIES => theme.bad,
+                _ => theme.value,
+            },
+            ExtraColumn::Error => theme.bad,
+            // A record set in this run stands out.
+            ExtraColumn::Record => match &config.records {
+                Some(records) if records.beaten(stat) => theme.good,
+                _ => theme.value,
+            },
+            ExtraColumn::Baseline => match baseline_delta(stat, config) {
+                BaselineDelta::Change(delta) if delta < 0.0 => theme.good,
+                BaselineDelta::Change(delta) if delta > 0.0 => theme.bad,
+                _ => theme.value,
+            },
+        }
+    }
+}
+
+enum BaselineDelta {
+    /// The region isn't in the baseline.
+    New,
+    /// Current minus baseline average, in milliseconds.
+    Change(f64),
+    /// There's no baseline, or one side has no average.
+    Unknown,
+}
+
+fn baseline_delta(stat: &PingStats, config: &Config) -> BaselineDelta {
+    let Some(baseline) = &config.baseline else {
+        return BaselineDelta::Unknown;
+    };
+    match baseline.get(stat.region) {
+        None => BaselineDelta::New,
+        Some(&before) => match (stat.avg(), before) {
+            (Some(now), Some(before)) => BaselineDelta::Change(now - before),
+            _ => BaselineDelta::Unknown,
+        },
+    }
+}
+
+impl FromStr for ExtraColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cv" => Ok(ExtraColumn::Cv),
+            "ewma" => Ok(ExtraColumn::Ewma),
+            "retries" => Ok(ExtraColumn::Retries),
+            "iqr" => Ok(ExtraColumn::Iqr),
+            "baseline" => Ok(ExtraColumn::Baseline),
+            "sem" => Ok(ExtraColumn::Sem),
+            "mad" => Ok(ExtraColumn::Mad),
+            "error" => Ok(ExtraColumn::Error),
+            "record" => Ok(ExtraColumn::Record),
+            _ => Err(format!("unknown column '{}'", s)),
+        }
+    }
+}
+
+/// All column labels for the table: the defaults followed by any extras.
+pub fn column_labels(extra: &[ExtraColumn]) -> Vec<&'static str> {
+    COLUMN_LABELS
+        .iter()
+        .copied()
+        .chain(extra.iter().map(|column| column.label()))
+        .collect()
+}
+
+fn format_ratio(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.1}%", v * 100.0))
+        .unwrap_or("--".to_string())
+}
+
+/// Formats a latency in milliseconds with `precision` decimal places.
+pub fn format_latency(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|v| format!("{:.*} ms", precision, v))
+        .unwrap_or("--".to_string())
+}
+
+/// Latencies from which `--compact` switches to seconds.
+const COMPACT_FROM_MS: f64 = 1000.0;
+
+/// Like `format_latency`, but latencies of a second or more read as seconds
+/// with one decimal place, e.g. `1.2 s`, so slow regions keep the column
+/// narrow. The switch looks at the value as it would be printed, so nothing
+/// ever rounds up to `1000.00 ms`.
+pub fn format_compact_latency(value: Option<f64>, precision: usize) -> String {
+    match value {
+        Some(v) if round_to(v, precision) >= COMPACT_FROM_MS => format!("{:.1} s", v / 1000.0),
+        _ => format_latency(value, precision),
+    }
+}
+
+fn round_to(value: f64, precision: usize) -> f64 {
+    let scale = 10f64.powi(precision as i32);
+    (value * scale).round() / scale
+}
+
+/// Formats a byte count with a binary unit, e.g. `12.3 KiB`.
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Formats a run duration as mm:ss, or hh:mm:ss once it passes an hour.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if hours > 0 {
+        format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+/// Shown instead of a derived statistic until a region has `--min-samples`.
+const INSUFFICIENT: &str = "~";
+
+/// The formatted latency cells of a row, in `COLUMN_LABELS` order minus the
+/// region column. Everything but the last sample reads `~` while the region
+/// has fewer than `--min-samples` samples.
+pub fn latency_texts(stat: &PingStats, config: &Config) -> [String; 7] {
+    let format_latency = |value| {
+        if config.compact {
+            format_compact_latency(value, config.precision)
+        } else {
+            format_latency(value, config.precision)
+        }
+    };
+    if stat.samples < config.min_samples {
+        let mut texts = std::array::from_fn(|_| INSUFFICIENT.to_string());
+        texts[0] = format_latency(stat.last());
+        return texts;
+    }
+    [
+        format_latency(stat.last()),
+        format_latency(stat.min()),
+        format_latency(stat.avg()),
+        format_latency(stat.max()),
+        format_latency(stat.stddev()),
+        format_latency(stat.p95()),
+        format_latency(stat.p99()),
+    ]
+}
+
+/// The `--sort score` value of a region: its average latency plus
+/// `loss_penalty` milliseconds per percent of loss.
+pub fn sort_score(stat: &PingStats, loss_penalty: f64) -> Option<f64> {
+    let loss_pct = stat.loss().unwrap_or(0.0) * 100.0;
+    stat.avg().map(|avg| avg + loss_pct * loss_penalty)
+}
+
+/// The value regions are sorted by, lowest first.
+fn sort_value(stat: &PingStats, config: &Config) -> Option<f64> {
+    match config.sort {
+        SortKey::Avg => stat.avg(),
+        SortKey::Cv => stat.cv(),
+        SortKey::Ewma => stat.ewma(),
+        SortKey::Score => sort_score(stat, config.loss_penalty),
+        SortKey::Loss => stat.loss(),
+    }
+}
+
+/// A latency rounded to a multiple of `epsilon` milliseconds, so regions
+/// closer than that mostly compare equal and keep their order rather than
+/// swapping on every sample. Rounding, unlike comparing differences against
+/// `epsilon`, keeps the comparison a total order.
+fn quantize(value: Option<f64>, epsilon: f64) -> Option<f64> {
+    value.map(|v| {
+        if epsilon > 0.0 {
+            (v / epsilon).round()
+        } else {
+            v
+        }
+    })
+}
+
+/// Orders two regions by the sort key. Ties keep the regions' list order,
+/// since the sort is stable.
+fn compare_stat(a: &PingStats, b: &PingStats, config: &Config) -> std::cmp::Ordering {
+    let cmp =
+        |x: Option<f64>, y: Option<f64>| x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal);
+    let ms = |value: Option<f64>| quantize(value, config.sort_epsilon);
+    match config.sort {
+        SortKey::Cv => cmp(a.cv(), b.cv()),
+        // Strictly by loss first; latency only separates equal loss.
+        SortKey::Loss => cmp(a.loss(), b.loss()).then_with(|| cmp(ms(a.avg()), ms(b.avg()))),
+        SortKey::Avg | SortKey::Ewma | SortKey::Score => {
+            cmp(ms(sort_value(a, config)), ms(sort_value(b, config)))
+        }
+    }
+}
+
+/// The `--highlight-slowest` regions: the last ones in sort order among
+/// those with a value to sort by.
+pub fn slowest<'a>(stats: &[PingStats<'a>], config: &Config) -> HashSet<&'a str> {
+    let order = display_order(stats, &HashSet::new(), config);
+    order
+        .into_iter()
+        .filter(|&i| sort_value(&stats[i], config).is_some())
+        .rev()
+        .take(config.highlight_slowest)
+        .map(|i| stats[i].region)
+        .collect()
+}
+
+/// Returns indices into `stats` in display order: pinned regions first, then
+/// the rest, each block sorted by the configured sort key. Unpinned regions
+/// are left out when hidden by `--hide-unreachable`.
+pub fn display_order(stats: &[PingStats], pinned: &HashSet<&str>, config: &Config) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..stats.len())
+        .filter(|&i| {
+            !config.hide_unreachable
+                || pinned.contains(stats[i].region)
+                || !stats[i].is_unreachable()
+        })
+        .collect();
+    order.sort_by(|&a, &b| {
+        let a_pinned = pinned.contains(stats[a].region);
+        let b_pinned = pinned.contains(stats[b].region);
+        b_pinned
+            .cmp(&a_pinned)
+            .then_with(|| compare_stat(&stats[a], &stats[b], config))
+    });
+    order
+}
+
+/// Colors of the `--gradient` heatmap at the fastest, middle and slowest
+/// latencies.
+const GRADIENT: [(u8, u8, u8); 3] = [(0, 200, 0), (220, 200, 0), (220, 0, 0)];
+
+/// The `--gradient` color of `value` by where it falls between `min` and
+/// `max`, blending green into yellow into red.
+fn gradient_color(value: f64, (min, max): (f64, f64)) -> Color {
+    let t = if max > min {
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (from, to, t) = if t < 0.5 {
+        (GRADIENT[0], GRADIENT[1], t * 2.0)
+    } else {
+        (GRADIENT[1], GRADIENT[2], t * 2.0 - 1.0)
+    };
+    let mix = |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * t).round() as u8;
+    Color::Rgb(mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// The lowest and highest last latency across the table, which `--gradient`
+/// colors between.
+fn gradient_range(stats: &[PingStats], config: &Config) -> Option<(f64, f64)> {
+    if !config.gradient {
+        return None;
+    }
+    let lasts = stats.iter().filter_map(PingStats::last);
+    let min = lasts.clone().reduce(f64::min)?;
+    let max = lasts.reduce(f64::max)?;
+    Some((min, max))
+}
+
+/// How long the last cell blinks after a spike, and how long each blink is on
+/// and then off.
+const SPIKE_BLINK_FOR: Duration = Duration::from_secs(3);
+const SPIKE_BLINK_HALF_PERIOD: Duration = Duration::from_millis(300);
+
+/// Whether a cell marking a spike from `since` ago is lit right now: on for
+/// the first half of each blink, then dark for good once the blinking decays.
+fn spike_lit(since: Duration) -> bool {
+    since < SPIKE_BLINK_FOR
+        && (since.as_millis() / SPIKE_BLINK_HALF_PERIOD.as_millis()).is_multiple_of(2)
+}
+
+/// The text and style of a row's "Last" cell: the latest sample, or why it
+/// failed when the reason is worth showing.
+fn last_cell(
+    stat: &PingStats,
+    last_text: String,
+    marks: RowMarks,
+    config: &Config,
+) -> (String, Style) {
+    let theme = &config.theme;
+    let error_text = match stat.last_error {
+        Some(PingError::Dns) => Some("DNS error"),
+        Some(PingError::Unsupported) => Some("no HTTP/3"),
+        _ => None,
+    };
+    if let Some(text) = error_text {
+        return (text.to_string(), theme.bad);
+    }
+
+    let last_style = if let (Some(last), Some(range)) = (stat.last(), marks.gradient) {
+        theme
+            .value
+            .fg(config.theme.color(gradient_color(last, range)))
+    } else if let (Some(last), Some(avg)) = (stat.last(), stat.avg()) {
+        if last > avg {
+            theme.bad // Worse performance
+        } else {
+            theme.good // Better performance
+        }
+    } else {
+        theme.value
+    };
+    if marks.spike {
+        return (last_text, last_style.add_modifier(Modifier::REVERSED));
+    }
+    (last_text, last_style)
+}
+
+/// How a row is marked besides its values.
+#[derive(Clone, Copy)]
+struct RowMarks {
+    pinned: bool,
+    /// One of the `--highlight-slowest` regions.
+    slow: bool,
+    /// No successful sample for a while, so the values are out of date.
+    stale: bool,
+    /// The range of last latencies the last cell is colored across, with
+    /// `--gradient`.
+    gradient: Option<(f64, f64)>,
+    /// The last cell is lit by a recent spike, with `--spike-blink`.
+    spike: bool,
+}
+
+impl RowMarks {
+    /// The base style of the row, dimmed when its values are stale.
+    fn row_style(self) -> Style {
+        if self.stale {
+            Style::default().add_modifier(Modifier::DIM)
+        } else {
+            Style::default()
+        }
+    }
+}
+
+/// A region is stale once this many of its waits between probes pass
+/// without a sample, on top of the longest its probe may take.
+const STALE_INTERVALS: u32 = 3;
+
+/// Whether the region's last sample, failed or not, is old enough that its
+/// row no longer reflects the present, e.g. because its task died. How old
+/// follows the region's schedule: its longest wait between probes under
+/// `--adaptive`, `--fast-retry` and `--hide-unreachable`, and the time its
+/// probe takes when every attempt times out. Replays keep their recorded
+/// pacing, which says nothing about the present, so nothing is stale during
+/// one.
+fn is_stale(stat: &PingStats, config: &Config) -> bool {
+    if config.replay.is_some() {
+        return false;
+    }
+    let mut wait = if config.adaptive {
+        config.max_interval
+    } else {
+        config.min_interval
+    };
+    if let Some(fast_retry) = config.fast_retry {
+        wait = wait.max(fast_retry);
+    }
+    if config.hide_unreachable && stat.is_unreachable() {
+        wait = wait.max(UNREACHABLE_INTERVAL);
+    }
+    // Endpoints are probed together, so the slowest to time out sets the
+    // pace.
+    let timeout = config
+        .regions
+        .iter()
+        .find(|region| region.name == stat.region)
+        .and_then(|region| {
+            region
+                .targets
+                .iter()
+                .map(|target| target.timeout.unwrap_or(config.timeout))
+                .max()
+        })
+        .unwrap_or(config.timeout);
+    let threshold = wait
+        .saturating_mul(STALE_INTERVALS)
+        .saturating_add(ping::longest_ping(timeout, config.retries));
+    stat.last_sample()
+        .is_some_and(|at| at.elapsed() > threshold)
+}
+
+fn region_span(stat: &PingStats, marks: RowMarks, config: &Config) -> Span<'static> {
+    let mut style = if marks.slow {
+        config.theme.bad.add_modifier(Modifier::REVERSED)
+    } else {
+        config.theme.region
+    };
+    let mut prefix = String::new();
+    if marks.pinned {
+        style = style.add_modifier(Modifier::BOLD);
+        prefix.push_str(PIN_MARKER);
+    }
+    // A flag is a pair of regional indicator symbols. Ratatui counts the pair
+    // as two cells wide, the same as terminals draw it, so columns stay
+    // aligned.
+    if let Some(flag) = config.flags.then(|| regions::flag(stat.region)).flatten() {
+        prefix.push_str(flag);
+        prefix.push(' ');
+    }
+
+    let mut name = prefix + config.display_name(stat.region);
+    if marks.stale {
+        name.push_str(" (stale)");
+    }
+    // Percentiles settle once the window fills, so until then show how far
+    // along it is.
+    if let Some((len, size)) = stat.window_fill().filter(|_| config.window_fill) {
+        if len < size {
+            name.push_str(&format!(" {}/{}", len, size));
+        }
+    }
+    Span::styled(name, style)
+}
+
+fn row_for_stat<'a>(stat: &'a PingStats, marks: RowMarks, config: &Config) -> Row<'a> {
+    let theme = &config.theme;
+    let [last_text, min_text, avg_text, max_text, stddev_text, p95_text, p99_text] =
+        latency_texts(stat, config);
+    let (last_text, last_style) = last_cell(stat, last_text, marks, config);
+
+    let mut cells = vec![
+        Cell::from(region_span(stat, marks, config)),
+        Cell::from(Span::styled(last_text, last_style)),
+        Cell::from(Span::styled(min_text, theme.value)),
+        Cell::from(Span::styled(avg_text, theme.value)),
+        Cell::from(Span::styled(max_text, theme.value)),
+        Cell::from(Span::styled(stddev_text, theme.value)),
+        Cell::from(Span::styled(p95_text, theme.value)),
+        Cell::from(Span::styled(p99_text, theme.value)),
+    ];
+    cells.extend(config.columns.iter().map(|column| {
+        Cell::from(Span::styled(
+            column.text(stat, config),
+            column.style(stat, config),
+        ))
+    }));
+
+    Row::new(cells).style(marks.row_style())
+}
+
+/// A two-line row for the stacked layout: the region, then its last sample,
+/// average and loss.
+fn stacked_row_for_stat<'a>(stat: &'a PingStats, marks: RowMarks, config: &Config) -> Row<'a> {
+    let theme = &config.theme;
+    let [last_text, _, avg_text, ..] = latency_texts(stat, config);
+    let (last_text, last_style) = last_cell(stat, last_text, marks, config);
+    let stats_line = Line::from(vec![
+        Span::styled(last_text, last_style),
+        Span::styled(" · avg ", theme.header),
+        Span::styled(avg_text, theme.value),
+        Span::styled(" · loss ", theme.header),
+        Span::styled(format_ratio(stat.loss()), theme.value),
+    ]);
+    Row::new([Cell::from(vec![
+        Line::from(region_span(stat, marks, config)),
+        stats_line,
+    ])])
+    .style(marks.row_style())
+    .height(2)
+}
+
+/// Lines shown in the detail panel for the selected region.
+fn detail_lines(stat: &PingStats, config: &Config) -> Vec<Line<'static>> {
+    let weights = config.recommend_weights;
+    let window = format_elapsed(config.stats.history_window);
+    let score_line = match score(stat, weights) {
+        Some(score) => format!(
+            "Score {:.1} = {} × {} median + {} × {:.1}% loss (last {})",
+            score.value,
+            weights.latency,
+            format_latency(Some(score.median), config.precision),
+            weights.loss,
+            score.loss_pct,
+            window
+        ),
+        None => format!("Score: no successful samples in the last {}", window),
+    };
+
+    let outage_line = match stat.longest_outage() {
+        Some(outage) => format!("Longest outage {:.1}s", outage.as_secs_f64()),
+        None => "No failed pings".to_string(),
+    };
+
+    let range_line = format!(
+        "Best ever {} · worst ever {} · recent min {} · recent max {}",
+        format_latency(stat.session_min(), config.precision),
+        format_latency(stat.session_max(), config.precision),
+        format_latency(stat.min(), config.precision),
+        format_latency(stat.max(), config.precision)
+    );
+
+    // 1.96 standard errors either side covers 95% of a normal distribution.
+    // Latencies are skewed, but the mean of enough of them is close to normal.
+    let interval_line = match (stat.avg(), stat.sem()) {
+        (Some(avg), Some(sem)) => format!(
+            "Mean {} ± {} (95% CI, normal approximation)",
+            format_latency(Some(avg), config.precision),
+            format_latency(Some(1.96 * sem), config.precision)
+        ),
+        _ => "Mean: need 2 samples for a confidence interval".to_string(),
+    };
+
+    // A renamed region keeps its code in the details, for matching it
+    // against reports.
+    let heading = match config.display_name(stat.region) {
+        name if name == stat.region => name.to_string(),
+        name => format!("{} ({})", name, stat.region),
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(heading, config.theme.region)),
+        Line::from(Span::styled(score_line, config.theme.value)),
+        Line::from(Span::styled(interval_line, config.theme.value)),
+    ];
+    if config.sort == SortKey::Score {
+        let sort_line = match sort_score(stat, config.loss_penalty) {
+            Some(value) => format!(
+                "Sort score {:.1} = {} avg + {} × {:.1}% loss",
+                value,
+                format_latency(stat.avg(), config.precision),
+                config.loss_penalty,
+                stat.loss().unwrap_or(0.0) * 100.0
+            ),
+            None => "Sort score: no successful samples".to_string(),
+        };
+        lines.push(Line::from(Span::styled(sort_line, config.theme.value)));
+    }
+    if config.throughput {
+        let throughput_line = match (stat.last_bytes, stat.throughput()) {
+            (Some(bytes), Some(rate)) => format!(
+                "Last response {} · ~{}/s including the round trip",
+                format_bytes(bytes as f64),
+                format_bytes(rate)
+            ),
+            _ => "Last response: size not measured yet".to_string(),
+        };
+        lines.push(Line::from(Span::styled(
+            throughput_line,
+            config.theme.value,
+        )));
+    }
+    lines.push(Line::from(Span::styled(range_line, config.theme.value)));
+    let kind = config
+        .regions
+        .iter()
+        .find(|region| region.name == stat.region)
+        .and_then(regions::endpoint_kind);
+    if let Some(kind) = kind {
+        let kind_line = format!("Measured via {} endpoint", kind);
+        lines.push(Line::from(Span::styled(kind_line, config.theme.value)));
+    }
+    // Compare the cold first sample against the median once there are warm
+    // samples to compare with.
+    if let (Some(first), Some(median)) = (stat.first(), stat.median().filter(|_| stat.samples > 1))
+    {
+        let warmup_line = format!(
+            "First sample {} (cold) vs median {} (warm), {:+.*} ms",
+            format_latency(Some(first), config.precision),
+            format_latency(Some(median), config.precision),
+            config.precision,
+            first - median
+        );
+        lines.push(Line::from(Span::styled(warmup_line, config.theme.value)));
+    }
+    lines.push(Line::from(Span::styled(outage_line, config.theme.value)));
+    lines
+}
+
+/// Trailing moving average of `series` over `window` points; the first few
+/// points average over what's available so the output is as long as the
+/// input.
+fn smooth(series: &[f64], window: usize) -> Vec<f64> {
+    let window = window.max(1);
+    let mut sum = 0.0;
+    series
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            sum += value;
+            if i >= window {
+                sum -= series[i - window];
+            }
+            sum / (i + 1).min(window) as f64
+        })
+        .collect()
+}
+
+/// Labels of the markers on the percentile ruler, in the order `tail`
+/// returns them.
+const RULER_LABELS: [&str; 4] = ["P50", "P90", "P95", "P99"];
+
+/// Lines drawn by the detail panel besides `detail_lines`: the percentile
+/// ruler and the trend.
+const DETAIL_EXTRA_LINES: u16 = 3;
+
+/// A ruler `width` cells wide running from `min` to `max`, with a tick at
+/// each of `marks` and its label beneath. Labels that would run into the one
+/// before are left out, though their ticks stay.
+fn ruler_lines(min: f64, max: f64, marks: [f64; 4], width: usize) -> [String; 2] {
+    if width < 2 {
+        return [String::new(), String::new()];
+    }
+    let position = |value: f64| {
+        let fraction = if max > min {
+            ((value - min) / (max - min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (fraction * (width - 1) as f64).round() as usize
+    };
+    let mut ruler: Vec<char> = vec!['─'; width];
+    ruler[0] = '├';
+    ruler[width - 1] = '┤';
+    let mut labels: Vec<char> = vec![' '; width];
+    let mut free_from = 0;
+    for (&value, label) in marks.iter().zip(RULER_LABELS) {
+        let at = position(value);
+        ruler[at] = '┼';
+        // Labels start at their tick, or end at the right edge.
+        let start = at.min(width.saturating_sub(label.len()));
+        if start >= free_from && start + label.len() <= width {
+            labels[start..start + label.len()].copy_from_slice(&label.chars().collect::<Vec<_>>());
+            free_from = start + label.len() + 1;
+        }
+    }
+    [ruler.into_iter().collect(), labels.into_iter().collect()]
+}
+
+fn draw_detail(f: &mut Frame, area: Rect, stat: &PingStats, config: &Config) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(config.theme.border)
+        .title("Details");
+    let [text_area, ruler_area, trend_area] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(2),
+        Constraint::Length(1),
+    ])
+    .areas(block.inner(area));
+    f.render_widget(block, area);
+    f.render_widget(Paragraph::new(detail_lines(stat, config)), text_area);
+
+    // Where the tail percentiles fall between the fastest and slowest sample
+    // in the window.
+    if let (Some(min), Some(max), Some(tail)) = (stat.min(), stat.max(), stat.tail()) {
+        let [ruler, labels] = ruler_lines(min, max, tail, usize::from(ruler_area.width));
+        let lines = vec![
+            Line::from(Span::styled(ruler, config.theme.border)),
+            Line::from(Span::styled(labels, config.theme.value)),
+        ];
+        f.render_widget(Paragraph::new(lines), ruler_area);
+    }
+
+    // The trend of the latest samples, one per column, smoothed with
+    // `--smooth` so the shape is legible while the numbers stay exact.
+    let latencies: Vec<f64> = stat.latencies().collect();
+    let recent = &latencies[latencies
+        .len()
+        .saturating_sub(usize::from(trend_area.width))..];
+    let data: Vec<u64> = smooth(recent, config.smooth)
+        .into_iter()
+        .map(|ms| (ms * 1000.0) as u64)
+        .collect();
+    f.render_widget(
+        Sparkline::default().data(&data).style(config.theme.good),
+        trend_area,
+    );
+}
+
+/// Widths of the table's `count` columns. By default they share the width by
+/// percentage. `--column-widths` fixes them instead, and the columns that
+/// don't fit in `available` cells are left out from the right; the region
+/// column always stays.
+fn column_constraints(count: usize, available: u16, config: &Config) -> Vec<Constraint> {
+    let Some(widths) = &config.column_widths else {
+        return std::iter::once(Constraint::Percentage(20))
+            .chain(std::iter::repeat_n(Constraint::Percentage(10), count - 1))
+            .collect();
+    };
+    let mut used = 0u16;
+    let mut constraints = Vec::new();
+    for i in 0..count {
+        let width = widths[i.min(widths.len() - 1)];
+        // Columns after the first are separated by one cell.
+        let needed = used.saturating_add(width.saturating_add(u16::from(i > 0)));
+        if i > 0 && needed > available {
+            break;
+        }
+        used = needed;
+        constraints.push(Constraint::Length(width));
+    }
+    constraints
+}
+
+/// Formats a compared metric: loss as a percentage, the rest as latencies.
+fn format_metric(label: &str, value: Option<f64>, config: &Config) -> String {
+    match (label, value) {
+        ("Loss", Some(loss)) => format!("{:.1}%", loss * 100.0),
+        _ => format_latency(value, config.precision),
+    }
+}
+
+/// Two regions side by side, a metric per row, with the difference and the
+/// region that does better on each.
+fn draw_comparison(f: &mut Frame, area: Rect, a: &PingStats, b: &PingStats, config: &Config) {
+    let theme = &config.theme;
+    let (name_a, name_b) = (config.display_name(a.region), config.display_name(b.region));
+    let rows: Vec<Row> = COMPARED_METRICS
+        .iter()
+        .map(|&(label, metric)| {
+            let (value_a, value_b) = (metric(a), metric(b));
+            let (delta, better) = match (value_a, value_b) {
+                (Some(x), Some(y)) => {
+                    let better = match x.total_cmp(&y) {
+                        std::cmp::Ordering::Less => Some(name_a),
+                        std::cmp::Ordering::Greater => Some(name_b),
+                        std::cmp::Ordering::Equal => None,
+                    };
+                    let delta = format_metric(label, Some((y - x).abs()), config);
+                    (delta, better.unwrap_or("tie"))
+                }
+                _ => ("--".to_string(), "--"),
+            };
+            let style_for = |name: &str| {
+                if better == name {
+                    theme.good
+                } else {
+                    theme.value
+                }
+            };
+            Row::new([
+                Cell::from(label).style(theme.region),
+                Cell::from(format_metric(label, value_a, config)).style(style_for(name_a)),
+                Cell::from(format_metric(label, value_b, config)).style(style_for(name_b)),
+                Cell::from(delta).style(theme.value),
+                Cell::from(better.to_string()).style(theme.good),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(12),
+            Constraint::Percentage(22),
+            Constraint::Percentage(22),
+            Constraint::Percentage(14),
+            Constraint::Percentage(30),
+        ],
+    )
+    .header(
+        Row::new([
+            "Metric".to_string(),
+            name_a.to_string(),
+            name_b.to_string(),
+            "Δ".to_string(),
+            "Better".to_string(),
+        ])
+        .style(theme.header),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.border)
+            .title("Comparison"),
+    );
+    f.render_widget(table, area);
+}
+
+fn draw_recommendation(
+    f: &mut Frame,
+    area: Rect,
+    stats: &[PingStats],
+    leader: &mut Leader,
+    config: &Config,
+) {
+    let text = match leader.update(stats, config.recommend_weights) {
+        Some((stat, score)) => Line::from(vec![
+            Span::styled("Recommended: ", config.theme.header),
+            Span::styled(
+                config.display_name(stat.region).to_string(),
+                config.theme.good,
+            ),
+            Span::styled(format!(" (score {:.1})", score.value), config.theme.value),
+        ]),
+        None => Line::from(Span::styled(
+            "Recommended: waiting for samples",
+            config.theme.value,
+        )),
+    };
+    let panel = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(config.theme.border),
+    );
+    f.render_widget(panel, area);
+}
+
+fn draw_histogram(f: &mut Frame, area: Rect, stat: &PingStats, config: &Config) {
+    let histogram = stat.histogram(config.histogram_bins);
+    let labels: Vec<String> = histogram
+        .iter()
+        .map(|(lower, _)| format!("{:.0}", lower))
+        .collect();
+    let data: Vec<(&str, u64)> = labels
+        .iter()
+        .zip(&histogram)
+        .map(|(label, &(_, count))| (label.as_str(), count))
+        .collect();
+
+    let bins = config.histogram_bins as u16;
+    let inner_width = area.width.saturating_sub(2);
+    let bar_width = (inner_width.saturating_sub(bins.saturating_sub(1)) / bins.max(1)).max(1);
+
+    let chart = BarChart::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(config.theme.border)
+                .title("Latency distribution (ms)"),
+        )
+        .data(data.as_slice())
+        .bar_width(bar_width)
+        .bar_gap(1)
+        .bar_style(config.theme.good)
+        .value_style(config.theme.highlight)
+        .label_style(config.theme.value);
+    f.render_widget(chart, area);
+}
+
+/// One line summarizing every region with samples: the median of their
+/// medians and the best and worst averages.
+fn summary_line(stats: &[PingStats], config: &Config) -> Option<String> {
+    let mut medians: Vec<f64> = stats.iter().filter_map(PingStats::median).collect();
+    if medians.is_empty() {
+        return None;
+    }
+    medians.sort_by(f64::total_cmp);
+    let median = percentile(&medians, 50.0, config.stats.percentile_method);
+
+    let averages = stats
+        .iter()
+        .filter_map(|stat| stat.avg().map(|avg| (stat.region, avg)));
+    let (best_region, best) = averages.clone().min_by(|a, b| a.1.total_cmp(&b.1))?;
+    let (worst_region, worst) = averages.max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    Some(format!(
+        "Median of medians {} · best avg {} ({}) · worst avg {} ({})",
+        format_latency(Some(median), config.precision),
+        format_latency(Some(best), config.precision),
+        config.display_name(best_region),
+        format_latency(Some(worst), config.precision),
+        config.display_name(worst_region)
+    ))
+}
+
+fn draw_too_small(f: &mut Frame, min_width: u16, config: &Config) {
+    let area = f.area();
+    let message = format!(
+        "Terminal too small ({}x{}), need at least {}x{}.",
+        area.width, area.height, min_width, MIN_HEIGHT
+    );
+    let notice = Paragraph::new(message)
+        .style(config.theme.value)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(notice, area);
+}
+
+/// Every key the TUI handles and what it does, as listed by the `?` overlay.
+/// Keep in step with the key handling in `run_tui`.
+pub const KEY_BINDINGS: [(&str, &str); 18] = [
+    ("↑ k / ↓ j", "select the previous / next region"),
+    ("PgUp / PgDn", "select a page up / down"),
+    ("Home / End", "select the first / last region"),
+    ("Esc", "deselect"),
+    ("p", "pin the selected region to the top"),
+    ("v", "mark the selected region for comparison"),
+    ("d", "show the selected region's distribution"),
+    ("r", "re-ping the selected region now"),
+    ("c", "clear the selected region's stats"),
+    ("C", "clear every region's stats"),
+    ("f", "freeze / unfreeze the row order"),
+    ("o", "re-sort a frozen order once"),
+    ("s", "save a screenshot"),
+    ("b", "turn --sonar beeps on / off"),
+    ("S", "start a storm of extra probes, then compare"),
+    ("?", "show this help"),
+    ("q", "quit"),
+    ("Ctrl+C", "quit"),
+];
+
+/// Draws the key bindings in a box centered over whatever is on screen.
+fn draw_help(f: &mut Frame, config: &Config) {
+    let key_width = KEY_BINDINGS
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Line> = KEY_BINDINGS
+        .iter()
+        .map(|(key, action)| {
+            Line::from(vec![
+                Span::styled(format!("{:<1$}  ", key, key_width), config.theme.header),
+                Span::styled(*action, config.theme.value),
+            ])
+        })
+        .collect();
+    let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 4;
+    let height = lines.len() as u16 + 2;
+
+    let area = f.area();
+    let overlay = Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width: width.min(area.width),
+        height: height.min(area.height),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(config.theme.border)
+        .title("Keys (any key to close)");
+    f.render_widget(Clear, overlay);
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .style(config.theme.region),
+        overlay,
+    );
+}
+
+/// Frames of the `--warmup` spinner, one per tenth of a second.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Draws the `--warmup` countdown in place of the table. Seconds round up,
+/// so the countdown never reads 0 while it's still showing.
+pub fn draw_warmup(f: &mut Frame, remaining: Duration, config: &Config) {
+    let area = f.area();
+    let frame = SPINNER[(remaining.as_millis() / 100) as usize % SPINNER.len()];
+    let message = format!(
+        "{} Warming up, {}s left",
+        frame,
+        remaining.as_secs_f64().ceil()
+    );
+    let middle = Rect {
+        y: area.y + area.height / 2,
+        height: area.height.min(1),
+        ..area
+    };
+    let notice = Paragraph::new(message)
+        .style(config.theme.value)
+        .alignment(Alignment::Center);
+    f.render_widget(notice, middle);
+}
+
+/// Draws the whole TUI. Also updates the scroll position in `app`, which
+/// follows the selection and depends on how many rows fit.
+pub fn draw_table(f: &mut Frame, stats: &[PingStats], app: &mut App, config: &Config) {
+    let stacked = match config.layout {
+        LayoutMode::Auto => f.area().width < STACKED_BELOW_WIDTH,
+        LayoutMode::Table => false,
+        LayoutMode::Stacked => true,
+    };
+    let min_width = if stacked {
+        MIN_STACKED_WIDTH
+    } else {
+        MIN_WIDTH
+    };
+    if f.area().width < min_width || f.area().height < MIN_HEIGHT {
+        draw_too_small(f, min_width, config);
+        return;
+    }
+
+    let selected = app
+        .selected
+        .and_then(|region| stats.iter().find(|stat| stat.region == region));
+    // Optional panels are dropped, histogram first, rather than squeezing the
+    // table below a readable height.
+    let banner_height = u16::from(config.insecure);
+    let mut spare = (f.area().height - MIN_HEIGHT).saturating_sub(banner_height);
+    let mut fit = |height: u16| {
+        if height <= spare {
+            spare -= height;
+            height
+        } else {
+            0
+        }
+    };
+    let summary = summary_line(stats, config);
+    let summary_height = fit(u16::from(summary.is_some()));
+    // The detail panel's lines plus its borders, the ruler and the trend.
+    let detail_height = fit(selected.map_or(0, |stat| {
+        detail_lines(stat, config).len() as u16 + 2 + DETAIL_EXTRA_LINES
+    }));
+    let histogram_height = match selected {
+        Some(_) if app.show_histogram => fit(HISTOGRAM_HEIGHT),
+        _ => 0,
+    };
+    let compared = match app.compared.as_slice() {
+        [a, b] => stats
+            .iter()
+            .find(|stat| stat.region == *a)
+            .zip(stats.iter().find(|stat| stat.region == *b)),
+        _ => None,
+    };
+    let comparison_height = match compared {
+        Some(_) => fit(COMPARISON_HEIGHT),
+        None => 0,
+    };
+
+    let [banner_area, table_area, comparison_area, detail_area, histogram_area, recommendation_area, summary_area, footer_area] =
+        Layout::vertical([
+            Constraint::Length(banner_height),
+            Constraint::Min(0),
+            Constraint::Length(comparison_height),
+            Constraint::Length(detail_height),
+            Constraint::Length(histogram_height),
+            Constraint::Length(3),
+            Constraint::Length(summary_height),
+            Constraint::Length(1),
+        ])
+        .areas(f.area());
+
+    if config.insecure {
+        let banner = Paragraph::new(
+            "⚠ TLS certificate validation is disabled (--insecure); responses may be spoofed",
+        )
+        .style(
+            config
+                .theme
+                .bad
+                .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        );
+        f.render_widget(banner, banner_area);
+    }
+
+    let order = app.order(stats, config);
+
+    // Only the rows that fit are built, scrolled to keep the selection in view.
+    // The chrome is the borders plus, for the table, its header; the
+    // scrollbar is drawn over the right border beside the rows.
+    let chrome = if stacked { 2 } else { 3 };
+    let row_height = if stacked { 2 } else { 1 };
+    let visible = (usize::from(table_area.height.saturating_sub(chrome)) / row_height).max(1);
+    let position = app
+        .selected
+        .and_then(|region| order.iter().position(|&i| stats[i].region == region));
+    if let Some(position) = position {
+        app.scroll = app
+            .scroll
+            .clamp((position + 1).saturating_sub(visible), position);
+    }
+    app.scroll = app.scroll.min(order.len().saturating_sub(visible));
+    app.page_rows = visible;
+    let shown = &order[app.scroll..order.len().min(app.scroll + visible)];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(config.theme.border)
+        .title(config.title.as_str());
+    let slow = slowest(stats, config);
+    let gradient = gradient_range(stats, config);
+    let now = Instant::now();
+    let marks = |i: usize| RowMarks {
+        pinned: app.pinned.contains(stats[i].region),
+        slow: slow.contains(stats[i].region),
+        stale: is_stale(&stats[i], config),
+        gradient,
+        spike: stats[i]
+            .last_spike()
+            .is_some_and(|at| spike_lit(now.duration_since(at))),
+    };
+    let table = if stacked {
+        let rows: Vec<Row> = shown
+            .iter()
+            .map(|&i| stacked_row_for_stat(&stats[i], marks(i), config))
+            .collect();
+        Table::new(rows, [Constraint::Percentage(100)]).block(block)
+    } else {
+        let rows: Vec<Row> = shown
+            .iter()
+            .map(|&i| row_for_stat(&stats[i], marks(i), config))
+            .collect();
+        let available = table_area
+            .width
+            .saturating_sub(2 + config.theme.highlight_symbol.chars().count() as u16);
+        let widths = column_constraints(8 + config.columns.len(), available, config);
+        Table::new(rows, widths).block(block).header(
+            Row::new(column_labels(&config.columns).into_iter().map(Cell::from))
+                .style(config.theme.header),
+        )
+    }
+    .highlight_style(config.theme.highlight)
+    .highlight_symbol(config.theme.highlight_symbol);
+
+    let mut state =
+        TableState::default().with_selected(position.map(|position| position - app.scroll));
+
+    f.render_stateful_widget(table, table_area, &mut state);
+
+    let scrolls = order.len() > visible;
+    if scrolls {
+        let mut scrollbar_state =
+            ScrollbarState::new(order.len() - visible + 1).position(app.scroll);
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .style(config.theme.border)
+                .begin_symbol(None)
+                .end_symbol(None),
+            Rect {
+                y: table_area.y + chrome - 1,
+                height: table_area.height.saturating_sub(chrome),
+                ..table_area
+            },
+            &mut scrollbar_state,
+        );
+    }
+
+    if let Some((a, b)) = compared {
+        draw_comparison(f, comparison_area, a, b, config);
+    }
+    if let Some(stat) = selected {
+        draw_detail(f, detail_area, stat, config);
+        if app.show_histogram {
+            draw_histogram(f, histogram_area, stat, config);
+        }
+    }
+    draw_recommendation(f, recommendation_area, stats, &mut app.leader, config);
+    if let Some(summary) = summary {
+        f.render_widget(
+            Paragraph::new(summary).style(config.theme.value),
+            summary_area,
+        );
+    }
+
+    let mut footer = format!("running {}", format_elapsed(app.started.elapsed()));
+    if let Some(rate) = app.sample_rate() {
+        footer.push_str(&format!(" · {:.1} samples/s", rate));
+    }
+    if scrolls {
+        footer.push_str(&format!(
+            " · showing {}–{} of {}",
+            app.scroll + 1,
+            app.scroll + shown.len(),
+            order.len()
+        ));
+    }
+    let dropped = app.dropped.load(std::sync::atomic::Ordering::Relaxed);
+    if dropped > 0 {
+        footer.push_str(&format!(" · {} dropped", dropped));
+    }
+    if app.frozen_order.is_some() {
+        footer.push_str(" · sort frozen");
+    }
+    if let Some(hint) = app.flash().or(config.hint.as_deref()) {
+        footer.push_str(" · ");
+        footer.push_str(hint);
+    }
+    let footer = Paragraph::new(footer).style(config.theme.footer);
+    f.render_widget(footer, footer_area);
+
+    if app.show_help {
+        draw_help(f, config);
+    }
+}
+
+/// Whether `key` quits: `q`, or Ctrl+C. Ctrl+C must not also count as `c`,
+/// which would clear the selected region just before the final reports.
+pub fn quits(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regions::REGIONS_LIST;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    fn render(width: u16, height: u16, app: &mut App) -> String {
+        let config = Config::default();
+        let mut stats: Vec<PingStats> = REGIONS_LIST
+            .iter()
+            .map(|(region, _)| PingStats::new(region, config.stats))
+            .collect();
+        stats[0].add_latency(Some(Duration::from_millis(20)));
+
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal
+            .draw(|f| draw_table(f, &stats, app, &config))
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let mut text = String::new();
+        for y in 0..height {
+            for x in 0..width {
+                text.push_str(buffer[(x, y)].symbol());
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    #[test]
+    fn tiny_terminals_render_notice_without_panicking() {
+        let mut app = App::new();
+        app.selected = Some(REGIONS_LIST[0].0);
+        app.show_histogram = true;
+
+        for (width, height) in [
+            (1, 1),
+            (1, 50),
+            (200, 1),
+            (MIN_STACKED_WIDTH - 1, 50),
+            (200, MIN_HEIGHT - 1),
+        ] {
+            let text = render(width, height, &mut app);
+            assert!(!text.contains("Ping Latencies"), "{}x{}", width, height);
+        }
+        assert!(render(200, 5, &mut app).contains("Terminal too small"));
+    }
+
+    #[test]
+    fn minimum_size_renders_table() {
+        let mut app = App::new();
+        assert!(render(MIN_WIDTH, MIN_HEIGHT, &mut app).contains("Ping Latencies"));
+
+        // The detail and distribution panels are squeezed rather than
+        // overflowing when there isn't room for them.
+        app.selected = Some(REGIONS_LIST[0].0);
+        app.show_histogram = true;
+        assert!(render(MIN_WIDTH, MIN_HEIGHT, &mut app).contains("Ping Latencies"));
+    }
+
+    #[test]
+    fn help_overlay_lists_every_binding() {
+        let mut app = App::new();
+        app.show_help = true;
+        let text = render(120, 40, &mut app);
+        for (key, action) in KEY_BINDINGS {
+            assert!(text.contains(action), "{} missing", key);
+        }
+        // Squeezed terminals clip the overlay rather than panicking.
+        render(MIN_WIDTH, MIN_HEIGHT, &mut app);
+    }
+
+    #[test]
+    fn selection_scrolls_into_view() {
+        // Regions without samples sort first, so the only one with a sample
+        // is the last row.
+        let mut app = App::new();
+        let last = REGIONS_LIST[0].0;
+        app.selected = Some(last);
+
+        let text = render(120, 20, &mut app);
+        assert!(text.contains(last));
+        assert!(text.contains(&format!("of {}", REGIONS_LIST.len())));
+        assert!(app.scroll > 0);
+
+        app.selected = Some(REGIONS_LIST[1].0);
+        render(120, 20, &mut app);
+        assert_eq!(app.scroll, 0);
+    }
+
+    #[test]
+    fn configured_widths_hide_columns_that_dont_fit() {
+        let config = Config {
+            column_widths: Some(vec![30, 10]),
+            ..Config::default()
+        };
+        assert_eq!(
+            column_constraints(8, 60, &config),
+            vec![
+                Constraint::Length(30),
+                Constraint::Length(10),
+                Constraint::Length(10)
+            ]
+        );
+        // The region column stays even when nothing else fits.
+        assert_eq!(
+            column_constraints(8, 20, &config),
+            vec![Constraint::Length(30)]
+        );
+        // Widths that don't fit anywhere hide their columns rather than
+        // overflowing.
+        let config = Config {
+            column_widths: Some(vec![28, u16::MAX]),
+            ..Config::default()
+        };
+        assert_eq!(
+            column_constraints(8, 60, &config),
+            vec![Constraint::Length(28)]
+        );
+    }
+
+    #[test]
+    fn loss_sort_breaks_ties_by_latency() {
+        let config = Config {
+            sort: SortKey::Loss,
+            ..Config::default()
+        };
+        let region = |name, ms: &[u64], failures| {
+            let mut stat = PingStats::new(name, config.stats);
+            for &ms in ms {
+                stat.add_latency(Some(Duration::from_millis(ms)));
+            }
+            for _ in 0..failures {
+                stat.add_latency(None);
+            }
+            stat
+        };
+        let stats = [
+            region("fast-lossy", &[10, 10, 10], 1),
+            region("slow", &[90, 90, 90, 90], 0),
+            region("quick", &[40, 40, 40, 40], 0),
+        ];
+        let order: Vec<&str> = display_order(&stats, &HashSet::new(), &config)
+            .into_iter()
+            .map(|i| stats[i].region)
+            .collect();
+        assert_eq!(order, ["quick", "slow", "fast-lossy"]);
+    }
+
+    #[test]
+    fn near_equal_averages_keep_their_order() {
+        let config = Config {
+            sort_epsilon: 0.1,
+            ..Config::default()
+        };
+        let mut stats = [
+            PingStats::new("first", config.stats),
+            PingStats::new("second", config.stats),
+        ];
+        let order = |stats: &[PingStats<'static>]| -> Vec<&'static str> {
+            display_order(stats, &HashSet::new(), &config)
+                .into_iter()
+                .map(|i| stats[i].region)
+                .collect()
+        };
+        for tick in 0..10 {
+            // The lead swaps by a few microseconds every tick.
+            let (a, b) = if tick % 2 == 0 {
+                (20_010, 20_000)
+            } else {
+                (20_000, 20_010)
+            };
+            stats[0].add_latency(Some(Duration::from_micros(a)));
+            stats[1].add_latency(Some(Duration::from_micros(b)));
+            assert_eq!(order(&stats), ["first", "second"], "tick {}", tick);
+        }
+
+        // A real difference still reorders.
+        for _ in 0..10 {
+            stats[0].add_latency(Some(Duration::from_millis(30)));
+        }
+        assert_eq!(order(&stats), ["second", "first"]);
+    }
+
+    #[test]
+    fn compact_latencies_switch_to_seconds_as_printed() {
+        assert_eq!(format_compact_latency(Some(999.99), 2), "999.99 ms");
+        assert_eq!(format_compact_latency(Some(999.996), 2), "1.0 s");
+        assert_eq!(format_compact_latency(Some(999.6), 0), "1.0 s");
+        assert_eq!(format_compact_latency(Some(1234.56), 2), "1.2 s");
+        assert_eq!(format_compact_latency(None, 2), "--");
+    }
+
+    #[test]
+    fn gradient_blends_green_through_yellow_to_red() {
+        let range = (10.0, 30.0);
+        assert_eq!(gradient_color(10.0, range), Color::Rgb(0, 200, 0));
+        assert_eq!(gradient_color(20.0, range), Color::Rgb(220, 200, 0));
+        assert_eq!(gradient_color(30.0, range), Color::Rgb(220, 0, 0));
+        assert_eq!(gradient_color(15.0, range), Color::Rgb(110, 200, 0));
+        // Everything is fastest when every region is equally fast.
+        assert_eq!(gradient_color(5.0, (5.0, 5.0)), Color::Rgb(0, 200, 0));
+    }
+
+    #[test]
+    fn spikes_blink_then_decay() {
+        let ms = Duration::from_millis;
+        assert!(spike_lit(ms(0)));
+        assert!(spike_lit(ms(299)));
+        assert!(!spike_lit(ms(300)));
+        assert!(spike_lit(ms(600)));
+        assert!(spike_lit(ms(2500)));
+        assert!(!spike_lit(ms(3000)));
+        assert!(!spike_lit(ms(3600)));
+    }
+
+    #[test]
+    fn ruler_places_percentiles_between_min_and_max() {
+        let [ruler, labels] = ruler_lines(10.0, 30.0, [15.0, 20.0, 21.0, 30.0], 21);
+        assert_eq!(ruler, "├────┼────┼┼────────┼");
+        // P95 would run into P90's label, so only its tick shows.
+        assert_eq!(labels, "     P50  P90     P99");
+        assert_eq!(labels.chars().count(), 21);
+
+        // With every sample equal, everything sits at the start.
+        let [ruler, labels] = ruler_lines(5.0, 5.0, [5.0; 4], 8);
+        assert_eq!(ruler, "┼──────┤");
+        assert_eq!(labels, "P50     ");
+    }
+
+    #[test]
+    fn smoothing_averages_trailing_window() {
+        let series = [10.0, 20.0, 30.0, 100.0, 50.0];
+        assert_eq!(smooth(&series, 1), series);
+        assert_eq!(smooth(&series, 2), [10.0, 15.0, 25.0, 65.0, 75.0]);
+        assert_eq!(smooth(&series, 3), [10.0, 15.0, 20.0, 50.0, 60.0]);
+        assert!(smooth(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn regions_go_stale_once_their_schedule_is_overdue() {
+        // Three 1s waits plus two attempts timing out 2s apart with a retry
+        // delay between: 7.5s.
+        let config = Config {
+            timeout: Duration::from_secs(2),
+            retries: 1,
+            ..Config::default()
+        };
+        let ago = |secs| Instant::now() - Duration::from_secs(secs);
+        let mut stat = PingStats::new("test", config.stats);
+        assert!(!is_stale(&stat, &config));
+
+        // A failure is a sample too: the region is being probed.
+        stat.add_latency_at(Some(Duration::from_millis(10)), ago(60));
+        stat.add_latency_at(None, ago(6));
+        assert!(!is_stale(&stat, &config));
+
+        let mut stat = PingStats::new("test", config.stats);
+        stat.add_latency_at(None, ago(9));
+        assert!(is_stale(&stat, &config));
+        let patient = Config {
+            fast_retry: Some(Duration::from_secs(5)),
+            ..config
+        };
+        assert!(!is_stale(&stat, &patient));
+    }
+
+    #[test]
+    fn ctrl_c_quits_without_clearing() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert!(quits(&key(KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert!(quits(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        // Plain c clears the selected region instead.
+        assert!(!quits(&key(KeyCode::Char('c'), KeyModifiers::NONE)));
+        assert!(!quits(&key(KeyCode::Char('C'), KeyModifiers::SHIFT)));
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+/// Whether the TUI is still waiting out `--warmup` or showing the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Samples are being taken but not shown, until `until`.
+    Warmup {
+        until: Instant,
+    },
+    Running,
+}
+
+impl Phase {
+    /// Starts a warm-up lasting `warmup` from `now`. A zero warm-up starts
+    /// out running, so the countdown is never drawn, not even for one frame.
+    pub fn new(warmup: Duration, now: Instant) -> Self {
+        if warmup.is_zero() {
+            Phase::Running
+        } else {
+            Phase::Warmup {
+                until: now + warmup,
+            }
+        }
+    }
+
+    /// Moves on to running once the warm-up is over. Returns `true` only on
+    /// the call that makes the switch, so the caller can start the stats over
+    /// exactly once.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        match *self {
+            // Reaching `until` exactly ends the warm-up, so `remaining` never
+            // reports zero time left.
+            Phase::Warmup { until } if now >= until => {
+                *self = Phase::Running;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Time left in the warm-up as of `now`, or `None` once running. Always
+    /// non-zero when `advance` was called with the same `now` first.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        match *self {
+            Phase::Warmup { until } => Some(until.saturating_duration_since(now)),
+            Phase::Running => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_warmup_starts_running() {
+        let now = Instant::now();
+        let mut phase = Phase::new(Duration::ZERO, now);
+        assert_eq!(phase, Phase::Running);
+        assert_eq!(phase.remaining(now), None);
+        assert!(!phase.advance(now));
+    }
+
+    #[test]
+    fn warmup_ends_exactly_when_nothing_remains() {
+        let start = Instant::now();
+        let warmup = Duration::from_secs(2);
+        let mut phase = Phase::new(warmup, start);
+
+        let before = start + warmup - Duration::from_millis(1);
+        assert!(!phase.advance(before));
+        assert_eq!(phase.remaining(before), Some(Duration::from_millis(1)));
+
+        // A tick landing right on the boundary switches instead of drawing a
+        // countdown with nothing left.
+        let boundary = start + warmup;
+        assert!(phase.advance(boundary));
+        assert_eq!(phase.remaining(boundary), None);
+
+        // Later ticks, or one from before the switch arriving late, don't
+        // switch again.
+        assert!(!phase.advance(boundary + Duration::from_secs(1)));
+        assert!(!phase.advance(before));
+        assert_eq!(phase, Phase::Running);
+    }
+
+    #[test]
+    fn late_tick_skips_past_the_boundary() {
+        let start = Instant::now();
+        let mut phase = Phase::new(Duration::from_secs(1), start);
+        assert!(phase.advance(start + Duration::from_secs(5)));
+        assert_eq!(phase, Phase::Running);
+    }
+}